@@ -0,0 +1,148 @@
+//! Benchmarks of the engine hot paths, run with `cargo bench`.
+//!
+//! Every input is derived from fixed seeds, so runs on the same machine are
+//! comparable.
+
+use std::time::Duration;
+
+use bus::Bus;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tetris_auto::{
+    auto::{DepthLimit, TetrisAuto, TRAIN_SEED, TRAIN_WIDTH},
+    brick::Brick,
+    budget::SearchConfig,
+    fixed_heap::FixedHeap,
+    game::{decode_placement, GameState},
+    random::get_random_num,
+    vec2::Vec2,
+};
+
+/// Bricks placed greedily to reach the board the single-state benchmarks use.
+const MIDGAME_BRICKS: usize = 20;
+const EXPAND_SIZE: usize = 34;
+const BEAM_BRICKS: usize = 500;
+
+/// The same mid-game state on every run, placed greedily by `sp_score`.
+fn midgame() -> GameState {
+    let mut next_states = vec![GameState::default(); EXPAND_SIZE];
+    let mut state = GameState::initial_state();
+    for _ in 0..MIDGAME_BRICKS {
+        let len = state.next(&mut next_states);
+        state = next_states[..len].iter().max_by_key(|state| state.sp_score).unwrap().clone();
+    }
+    state
+}
+
+fn children(state: &GameState) -> Vec<GameState> {
+    let mut next_states = vec![GameState::default(); EXPAND_SIZE];
+    let len = state.next(&mut next_states);
+    next_states.truncate(len);
+    next_states
+}
+
+fn game_state(c: &mut Criterion) {
+    let state = midgame();
+    let mut next_states = vec![GameState::default(); EXPAND_SIZE];
+    c.bench_function("GameState::next", |b| b.iter(|| state.next(black_box(&mut next_states))));
+
+    let brick = Brick::from_random_num(get_random_num(state.rand_num), state.brick_count);
+    let placements = children(&state)
+        .iter()
+        .map(|child| decode_placement(*child.brick_stack.last().unwrap()))
+        .collect::<Vec<_>>();
+    c.bench_function("GameState::find_way", |b| {
+        b.iter(|| {
+            for (pos, rot) in &placements {
+                let mut brick = brick;
+                black_box(state.find_way(&mut brick, *rot, *pos));
+            }
+        })
+    });
+
+    // Undo the scoring of each child so `evaluate_score` sees the board right
+    // after the brick was placed, including full rows.
+    let placed = children(&state)
+        .into_iter()
+        .map(|mut child| {
+            let (pos, rot) = decode_placement(child.brick_stack.pop().unwrap());
+            child.grids = state.grids.clone();
+            child.grids.place_teris_brick(&brick.rotate_n(rot), pos);
+            child
+        })
+        .collect::<Vec<_>>();
+    c.bench_function("GameState::evaluate_score", |b| {
+        b.iter_batched_ref(
+            || placed.clone(),
+            |states| states.iter_mut().for_each(GameState::evaluate_score),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn grids(c: &mut Criterion) {
+    let state = midgame();
+    c.bench_function("GameGrids::remove_row", |b| {
+        b.iter_batched_ref(
+            || state.grids.clone(),
+            |grids| {
+                for y in (0..20).rev().step_by(3) {
+                    black_box(grids.remove_row(y));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    let brick = Brick::from_random_num(get_random_num(state.rand_num), state.brick_count);
+    c.bench_function("GameGrids::brick_pos_valid", |b| {
+        b.iter(|| {
+            for rot in 0..brick.state_count() {
+                let brick = brick.rotate_n(rot);
+                for y in 0..20 {
+                    for x in 0..10 {
+                        black_box(state.grids.brick_pos_valid(&brick, Vec2(x, y), true));
+                    }
+                }
+            }
+        })
+    });
+}
+
+fn fixed_heap(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(TRAIN_SEED);
+    let values = (0..100_000).map(|_| rng.gen::<i32>()).collect::<Vec<_>>();
+    let mut heap = FixedHeap::<i32>::new(10000);
+    c.bench_function("FixedHeap::push", |b| {
+        b.iter(|| {
+            heap.clear();
+            for value in &values {
+                black_box(heap.push(*value));
+            }
+        })
+    });
+}
+
+fn beam(c: &mut Criterion) {
+    let config = SearchConfig {
+        min_beam_width: TRAIN_WIDTH,
+        max_beam_width: TRAIN_WIDTH,
+        endgame_depth: 0,
+        seed: Some(TRAIN_SEED),
+        ..SearchConfig::default()
+    };
+    let mut kill_bus = Bus::new(1);
+    let mut kill_signal = kill_bus.add_rx();
+    let mut group = c.benchmark_group("beam");
+    group.sample_size(10).measurement_time(Duration::from_secs(90));
+    group.bench_function("500 bricks", |b| {
+        b.iter(|| {
+            let state = TetrisAuto::start(None, &config, &mut kill_signal, &mut DepthLimit(BEAM_BRICKS));
+            assert_eq!(state.brick_count, BEAM_BRICKS, "the beam died early");
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, game_state, grids, fixed_heap, beam);
+criterion_main!(benches);
@@ -0,0 +1,198 @@
+//! Every placement of the next two bricks of a position, to review why the
+//! search made a move.
+
+use crate::{
+    auto::TetrisAuto,
+    brick::Brick,
+    game::{decode_placement, push_placement_ops, GameState, MAX_PLACEMENTS},
+    op::{GameOP, GameOPStr},
+    random::get_random_num,
+};
+
+/// How deep and wide the optional search from each placement looks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeepSearch {
+    pub depth: usize,
+    pub width: usize,
+}
+
+/// One placement of a brick and what it leads to.
+pub struct PlacementInfo {
+    /// The `brick_stack` entry of the placement.
+    pub placement: u16,
+    /// The ops that reach it from the spawn, starting with the `N`.
+    pub path: Vec<GameOP>,
+    /// Score right after the placement.
+    pub score: u32,
+    pub sp_score: i32,
+    /// The best `sp_score` of a `DeepSearch` from the placement and how many
+    /// bricks it got to place, fewer than the depth if its beam died out.
+    pub deep: Option<(i32, usize)>,
+}
+
+/// The placements of one brick, best first: by the deep search value if there
+/// is one, then by `sp_score`.
+pub struct BrickPlacements {
+    pub brick: Brick,
+    /// The placement of the previous brick this one follows, `None` for the
+    /// brick of the analyzed position.
+    pub after: Option<u16>,
+    pub placements: Vec<PlacementInfo>,
+}
+
+pub struct Analysis {
+    pub deep_search: Option<DeepSearch>,
+    pub current: BrickPlacements,
+    /// The next brick on the board left by the best placement of `current`,
+    /// `None` if the current brick doesn't fit.
+    pub next: Option<BrickPlacements>,
+}
+
+/// List every placement of the brick `state` places next, and of the brick
+/// after it following the best of them.
+pub fn analyze(state: &GameState, deep_search: Option<DeepSearch>) -> Analysis {
+    let (current, best_child) = placements(state, None, deep_search);
+    let next = best_child.map(|child| placements(&child, child.brick_stack.last().copied(), deep_search).0);
+    Analysis { deep_search, current, next }
+}
+
+/// The placements of the next brick of `state` and the child of the best one.
+fn placements(
+    state: &GameState,
+    after: Option<u16>,
+    deep_search: Option<DeepSearch>,
+) -> (BrickPlacements, Option<GameState>) {
+    let brick = Brick::from_random_num(get_random_num(state.rand_num), state.brick_count);
+    let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+    let len = state.next(&mut next_states);
+    next_states.truncate(len);
+    let mut children = next_states
+        .into_iter()
+        .map(|child| {
+            let placement = *child.brick_stack.last().unwrap();
+            let mut path = Vec::new();
+            push_placement_ops(&mut path, placement);
+            let deep = deep_search.map(|search| {
+                let leaf = TetrisAuto::search_ahead(&child, search.depth, search.width)
+                    .unwrap_or_else(|| child.clone());
+                (leaf.sp_score, leaf.brick_count - child.brick_count)
+            });
+            let info = PlacementInfo {
+                placement,
+                path,
+                score: child.score,
+                sp_score: child.sp_score,
+                deep,
+            };
+            (info, child)
+        })
+        .collect::<Vec<_>>();
+    children.sort_by_key(|(info, _)| std::cmp::Reverse((info.deep.map(|(sp_score, _)| sp_score), info.sp_score)));
+    let best_child = children.first().map(|(_, child)| child.clone());
+    let placements = children.into_iter().map(|(info, _)| info).collect();
+    (BrickPlacements { brick, after, placements }, best_child)
+}
+
+fn placement_name(placement: u16) -> String {
+    let (pos, rot) = decode_placement(placement);
+    format!("{},{} r{}", pos.0, pos.1, rot)
+}
+
+impl Analysis {
+    /// Render the analysis as one table per brick.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = self.table(&self.current);
+        if let Some(next) = &self.next {
+            lines.push(String::new());
+            lines.extend(self.table(next));
+        }
+        lines
+    }
+
+    fn table(&self, bricks: &BrickPlacements) -> Vec<String> {
+        let title = match bricks.after {
+            Some(after) => format!("Next brick {} after {}", bricks.brick.shape_name(), placement_name(after)),
+            None => format!("Current brick {}", bricks.brick.shape_name()),
+        };
+        let deep_header = match self.deep_search {
+            Some(search) => format!("{:>16}", format!("deep {}x{}", search.depth, search.width)),
+            None => String::new(),
+        };
+        let mut lines = vec![
+            format!("{}, {} placements", title, bricks.placements.len()),
+            format!("rank  x,y rot       score  sp_score{}  path", deep_header),
+        ];
+        for (rank, info) in bricks.placements.iter().enumerate() {
+            let deep = match (self.deep_search, info.deep) {
+                (Some(search), Some((sp_score, depth))) if depth < search.depth => {
+                    format!("{:>16}", format!("{} ({} deep)", sp_score, depth))
+                }
+                (_, Some((sp_score, _))) => format!("{:>16}", sp_score),
+                _ => String::new(),
+            };
+            lines.push(format!(
+                "{:>4}  {:<10}{:>8}{:>10}{}  {}",
+                rank + 1,
+                placement_name(info.placement),
+                info.score,
+                info.sp_score,
+                deep,
+                info.path.to_op_string()
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        game::{GameState, MAX_PLACEMENTS},
+        game_play::Game,
+        op::GameOP,
+    };
+
+    use super::{analyze, DeepSearch};
+
+    #[test]
+    fn test_paths_reach_the_placements() {
+        let state = GameState::initial_state();
+        let analysis = analyze(&state, None);
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        assert_eq!(analysis.current.placements.len(), state.next(&mut next_states));
+        for info in &analysis.current.placements {
+            let mut ops = info.path.clone();
+            ops.push(GameOP::New);
+            let game = Game::from_ops(&ops).unwrap();
+            let mut placed = state.clone();
+            placed.apply_placement(info.placement);
+            assert_eq!(game.state().grids, placed.grids);
+            assert_eq!((info.score, info.sp_score), (placed.score, placed.sp_score));
+        }
+        assert!(analysis.current.placements.windows(2).all(|pair| pair[0].sp_score >= pair[1].sp_score));
+
+        let next = analysis.next.unwrap();
+        let best = analysis.current.placements[0].placement;
+        assert_eq!(next.after, Some(best));
+        let mut child = state.clone();
+        child.apply_placement(best);
+        assert_eq!(next.placements.len(), child.next(&mut next_states));
+    }
+
+    #[test]
+    fn test_deep_search() {
+        let search = DeepSearch { depth: 2, width: 8 };
+        let analysis = analyze(&GameState::initial_state(), Some(search));
+        let values = analysis
+            .current
+            .placements
+            .iter()
+            .map(|info| info.deep.unwrap())
+            .collect::<Vec<_>>();
+        assert!(values.iter().all(|(_, depth)| *depth == 2));
+        assert!(values.windows(2).all(|pair| pair[0].0 >= pair[1].0));
+        let lines = analysis.lines();
+        assert!(lines[1].contains("deep 2x8"));
+        assert!(lines.iter().any(|line| line.starts_with("Next brick")));
+    }
+}
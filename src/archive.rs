@@ -0,0 +1,189 @@
+use std::{fs, io};
+
+use rand::Rng;
+
+use crate::{
+    distributed::{decode_brick_stack, encode_brick_stack},
+    game::GameState,
+};
+
+/// Brick counts at which workers report their best states to the archive.
+pub const BRANCH_DEPTHS: &[usize] = &[2000, 5000, 8000];
+/// How many of the best beam states a worker reports at each branch depth.
+pub const ELITES_REPORTED: usize = 4;
+/// How many elites the archive keeps per branch depth.
+pub const ELITES_PER_DEPTH: usize = 16;
+
+pub struct Elite {
+    pub brick_stack: Vec<u16>,
+    pub score: u32,
+    pub sp_score: i32,
+}
+
+/// The best intermediate states seen at a few fixed depths, so that new
+/// searches can branch from a strong prefix instead of the empty board.
+pub struct EliteArchive {
+    depths: Vec<usize>,
+    capacity: usize,
+    elites: Vec<Vec<Elite>>,
+}
+
+impl EliteArchive {
+    pub fn new(depths: &[usize], capacity: usize) -> Self {
+        Self {
+            depths: depths.to_vec(),
+            capacity,
+            elites: depths.iter().map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elites.iter().map(|elites| elites.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn elites_at(&self, depth: usize) -> &[Elite] {
+        match self.depths.iter().position(|d| *d == depth) {
+            Some(idx) => &self.elites[idx],
+            None => &[],
+        }
+    }
+
+    /// Add `state` if it sits at a branch depth and ranks among the best there.
+    /// Returns whether the archive changed.
+    pub fn offer(&mut self, state: &GameState) -> bool {
+        let idx = match self.depths.iter().position(|depth| *depth == state.brick_count) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let elites = &mut self.elites[idx];
+        if elites.iter().any(|elite| elite.brick_stack == state.brick_stack) {
+            return false;
+        }
+        let pos = elites.partition_point(|elite| elite.sp_score >= state.sp_score);
+        if pos >= self.capacity {
+            return false;
+        }
+        elites.insert(
+            pos,
+            Elite {
+                brick_stack: state.brick_stack.clone(),
+                score: state.score,
+                sp_score: state.sp_score,
+            },
+        );
+        elites.truncate(self.capacity);
+        true
+    }
+
+    /// Pick a prefix to branch from: a random non-empty depth, or the empty
+    /// board with the same odds as each depth, then a random elite there
+    /// biased towards the best.
+    pub fn pick(&self, rng: &mut impl Rng) -> Option<&[u16]> {
+        let candidates = self
+            .elites
+            .iter()
+            .filter(|elites| !elites.is_empty())
+            .collect::<Vec<_>>();
+        let choice = rng.gen_range(0..=candidates.len());
+        let elites = candidates.get(choice)?;
+        let idx = rng.gen_range(0..elites.len()).min(rng.gen_range(0..elites.len()));
+        Some(&elites[idx].brick_stack)
+    }
+
+    /// The content of the file written by `save`, one prefix per line.
+    pub fn to_text(&self) -> String {
+        let mut content = String::new();
+        for elite in self.elites.iter().flatten() {
+            content.push_str(&encode_brick_stack(&elite.brick_stack));
+            content.push('\n');
+        }
+        content
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    /// Load a file written by `save`, each prefix is replayed to rebuild its
+    /// state and has to consist of valid placements.
+    pub fn load(&mut self, path: &str) -> io::Result<()> {
+        for line in fs::read_to_string(path)?.lines().filter(|line| !line.trim().is_empty()) {
+            let state = GameState::try_from_brick_stack(&decode_brick_stack(line)?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            self.offer(&state);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::game::{greedy_playout, GameState, MAX_PLACEMENTS};
+
+    use super::EliteArchive;
+
+    fn states(depth: usize) -> Vec<GameState> {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let len = greedy_playout(&GameState::initial_state(), depth - 1).next(&mut next_states);
+        next_states[..len].to_vec()
+    }
+
+    #[test]
+    fn test_offer_keeps_best() {
+        let mut archive = EliteArchive::new(&[2], 3);
+        assert!(!archive.offer(&states(1)[0]));
+        let mut candidates = states(2);
+        for (i, state) in candidates.iter_mut().enumerate() {
+            state.sp_score = i as i32;
+            archive.offer(state);
+        }
+        assert!(!archive.offer(&candidates[candidates.len() - 1]));
+        let kept = archive.elites_at(2).iter().map(|elite| elite.sp_score).collect::<Vec<_>>();
+        let n = candidates.len() as i32;
+        assert_eq!(kept, vec![n - 1, n - 2, n - 3]);
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut archive = EliteArchive::new(&[2], 4);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(archive.pick(&mut rng).is_none());
+        for state in states(2) {
+            archive.offer(&state);
+        }
+        let picked = (0..100).filter_map(|_| archive.pick(&mut rng)).collect::<Vec<_>>();
+        assert!(!picked.is_empty() && picked.len() < 100);
+        assert!(picked.iter().all(|brick_stack| brick_stack.len() == 2));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut archive = EliteArchive::new(&[2], 4);
+        for state in states(2) {
+            archive.offer(&state);
+        }
+        let path = std::env::temp_dir().join(format!("tetris_archive_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        archive.save(path).unwrap();
+        let mut loaded = EliteArchive::new(&[2], 4);
+        loaded.load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let brick_stacks = |archive: &EliteArchive| {
+            let mut stacks = archive.elites_at(2).iter().map(|elite| elite.brick_stack.clone()).collect::<Vec<_>>();
+            stacks.sort();
+            stacks
+        };
+        assert_eq!(brick_stacks(&archive), brick_stacks(&loaded));
+
+        std::fs::write(path, "0000ffff\n").unwrap();
+        let err = EliteArchive::new(&[2], 4).load(path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -1,121 +1,623 @@
-use crate::{game::MAX_BRICKS_COUNT, game_io::RenderGame, op::GameOPStr};
+use crate::{archive::{BRANCH_DEPTHS, ELITES_REPORTED}, budget::{thread_cpu_time, BeamScheduler, SearchConfig}, dashboard::{BeamStats, ChannelReporter, Dashboard, Progress, BEAM_REPORT_INTERVAL, REFRESH_INTERVAL, TOP_K}, distributed::{self, Coordinator}, endgame::{self, UNLIMITED_ENDGAME_TIME}, game::MAX_BRICKS_COUNT, game_io::RenderGame, op::GameOPStr};
 use bus::{Bus, BusReader};
-use rand::prelude::*;
+use rand::{prelude::*, rngs::StdRng};
 use std::{
+    cmp::Reverse,
+    collections::VecDeque,
+    fmt,
+    io,
     mem,
     sync::{
-        mpsc::{channel, Receiver, Sender},
+        mpsc::{channel, Receiver, RecvTimeoutError},
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use crate::{fixed_heap::FixedHeap, game::GameState, game_io::GameRenderer};
+use crate::{
+    fixed_heap::FixedHeap,
+    game::{Candidate, GameState, MAX_PLACEMENTS},
+    selection::{self, Child, Selection},
+};
 
 const HEAP_SIZE: usize = 10000;
-const EXPAND_SIZE: usize = 34;
+const COOP_HEAP_SIZE: usize = 100000;
+/// Capacity of the heap of each cooperative thread, in percent of an even
+/// share of the beam. The slack keeps most of the best children of a chunk
+/// that has more than its share of them.
+const COOP_WORKER_SHARE: usize = 125;
 const JITTER_RATE: f64 = 0.02;
+/// Beam used to suggest a placement in interactive play.
+pub const HINT_DEPTH: usize = 3;
+pub const HINT_WIDTH: usize = 256;
+/// Workload of `train_profile`. Narrower beams die early with this seed.
+pub const TRAIN_SEED: u64 = 2021;
+pub const TRAIN_WIDTH: usize = 4096;
+pub const TRAIN_BRICKS: usize = 500;
+/// Depth of the endgame search `train_profile` runs on its mid-game result.
+const PROFILE_ENDGAME_DEPTH: usize = 3;
+/// Bricks between the beams a search saves to go back to when it dies out.
+const SAVE_INTERVAL: usize = 50;
+/// Saved beams kept, the oldest is dropped.
+const SAVED_BEAMS: usize = 3;
+/// Retries a search makes without getting past its deepest dead end before it
+/// gives up.
+const MAX_RETRIES: usize = 8;
 
 pub struct TetrisAuto {}
 
+/// Receives the best state of the beam by `sp_score` after every layer.
+pub trait StateReporter {
+    fn report(&mut self, state: &GameState) -> io::Result<()>;
+
+    /// Receives the best states of the beam when it reaches one of the
+    /// `archive::BRANCH_DEPTHS`.
+    fn report_elites(&mut self, _states: &[&GameState]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Receives a summary of the beam every `dashboard::BEAM_REPORT_INTERVAL`.
+    fn report_beam(&mut self, _stats: &BeamStats) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Receives what the search did when none of its states could place
+    /// the next brick.
+    fn report_dead_end(&mut self, _dead_end: &DeadEnd) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Receives the best state and a summary of the part of the beam that
+    /// cooperative thread `thread` expands, in place of `report_beam`, every
+    /// `dashboard::BEAM_REPORT_INTERVAL`.
+    fn report_thread(&mut self, _thread: usize, _best: &GameState, _stats: &BeamStats) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A layer of the beam in which no state could place the next brick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeadEnd {
+    /// Bricks placed by the states of the beam that died.
+    pub brick_count: usize,
+    /// Dead ends since the search last got past its deepest one, this one
+    /// included.
+    pub attempt: usize,
+    /// The bricks placed by the saved beam the search went back to and its
+    /// minimum beam width from there on, `None` if it gave up.
+    pub restart: Option<(usize, usize)>,
+}
+
+impl fmt::Display for DeadEnd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "beam died at brick {}, ", self.brick_count)?;
+        match self.restart {
+            Some((brick_count, width)) => write!(
+                f,
+                "retry {} from brick {} with a new seed and width >= {}",
+                self.attempt, brick_count, width
+            ),
+            None => write!(f, "no complete game after {} retries", self.attempt - 1),
+        }
+    }
+}
+
+/// Stops a search once its beam has placed this many bricks.
+pub struct DepthLimit(pub usize);
+
+impl StateReporter for DepthLimit {
+    fn report(&mut self, state: &GameState) -> io::Result<()> {
+        match state.brick_count >= self.0 {
+            true => Err(io::Error::new(io::ErrorKind::Interrupted, "depth limit reached")),
+            false => Ok(()),
+        }
+    }
+}
+
 impl TetrisAuto {
-    pub fn run_continuous(threads: usize) -> (Bus<()>, JoinHandle<()>) {
-        let mut kill_bus = Bus::new(threads);
-        let (result_snd, result_rcv) = channel::<GameState>();
-        let (resource_sender, resource_receiver) = channel();
+    /// Run a coordinator on `listen_addr` (any free localhost port if `None`)
+    /// together with `threads` local workers connected to it, other workers may
+    /// join over TCP. With an unlimited budget every finished search is
+    /// restarted until killed, otherwise each worker runs once and the returned
+    /// handle finishes after the last result.
+    pub fn run_continuous(
+        threads: usize,
+        config: SearchConfig,
+        listen_addr: Option<&str>,
+        checkpoint: Vec<Vec<u16>>,
+        archive_path: Option<String>,
+        mut renderer: Box<dyn RenderGame + Send>,
+    ) -> io::Result<(Bus<()>, JoinHandle<GameState>)> {
+        let coordinator = Coordinator::bind(listen_addr.unwrap_or("127.0.0.1:0"), config)?
+            .with_checkpoint(checkpoint)
+            .with_archive(archive_path)?;
+        let addr = coordinator.local_addr()?;
+        let mut kill_bus = Bus::new(1);
+        let kill_rx = kill_bus.add_rx();
+
+        let coordinator_handle = thread::spawn(move || {
+            coordinator.run(kill_rx, |dashboard| {
+                renderer.render_lines(&dashboard.lines());
+                renderer.flush();
+            })
+        });
+
         for _ in 0..threads {
-            resource_sender.send(kill_bus.add_rx()).unwrap();
+            thread::spawn(move || distributed::run_worker(addr));
         }
 
-        let mut renderer = GameRenderer::new();
+        Ok((kill_bus, coordinator_handle))
+    }
+
+    /// Run a single search whose beam is shared by `threads` threads, see
+    /// `start_cooperative`.
+    pub fn run_cooperative(
+        threads: usize,
+        config: SearchConfig,
+        renderer: Box<dyn RenderGame + Send>,
+    ) -> (Bus<()>, JoinHandle<GameState>) {
+        let mut kill_bus = Bus::new(1);
+        let mut kill_rx = kill_bus.add_rx();
+        let (progress_snd, progress_rcv) = channel::<Progress>();
+        let render_handle = Self::spawn_dashboard(progress_rcv, config.selection, renderer);
+
+        thread::spawn(move || {
+            let mut reporter = ChannelReporter { worker: 0, sender: progress_snd };
+            let final_state = Self::start_cooperative(threads, &config, &mut kill_rx, &mut reporter);
+            reporter.report(&final_state).ok();
+        });
+
+        (kill_bus, render_handle)
+    }
 
-        // Result Renderer
-        let render_handle = thread::spawn(move || {
-            let mut best_state = GameState::default();
-            while let Ok(state) = result_rcv.recv() {
-                if state.score > best_state.score {
-                    best_state = state;
-                    renderer.render_game(&best_state);
+    /// Show the progress received on `progress_rcv` on a `Dashboard` until the
+    /// search finishes, then save the best game if it is complete.
+    fn spawn_dashboard(
+        progress_rcv: Receiver<Progress>,
+        selection: Selection,
+        mut renderer: Box<dyn RenderGame + Send>,
+    ) -> JoinHandle<GameState> {
+        thread::spawn(move || {
+            let mut dashboard = Dashboard::new().with_selection(selection);
+            let mut rendered_at = Instant::now();
+            loop {
+                match progress_rcv.recv_timeout(REFRESH_INTERVAL) {
+                    Ok(progress) => dashboard.apply(progress),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if rendered_at.elapsed() >= REFRESH_INTERVAL {
+                    renderer.render_lines(&dashboard.lines());
                     renderer.flush();
+                    rendered_at = Instant::now();
                 }
-                if best_state.brick_count == MAX_BRICKS_COUNT {
-                    let seqence = best_state.get_op_sequence().to_op_string();
-                    std::fs::write(format!("op_sequence_{}", best_state.score), seqence).unwrap();
+            }
+            renderer.render_lines(&dashboard.lines());
+            renderer.flush();
+            let best_state = dashboard.best().clone();
+            if best_state.brick_count == MAX_BRICKS_COUNT {
+                let seqence = best_state.get_op_sequence().to_op_string();
+                std::fs::write(format!("op_sequence_{}", best_state.score), seqence).unwrap();
+            }
+            best_state
+        })
+    }
+
+    pub fn start(
+        renderer: Option<&mut dyn RenderGame>,
+        config: &SearchConfig,
+        kill_signal: &mut BusReader<()>,
+        reporter: &mut impl StateReporter,
+    ) -> GameState {
+        Self::start_from(vec![GameState::initial_state()], renderer, config, kill_signal, reporter)
+    }
+
+    /// Run the beam search with `initial_states` as the first layer, drawing
+    /// the best state of the beam on `renderer` after every layer.
+    pub fn start_from(
+        initial_states: Vec<GameState>,
+        mut renderer: Option<&mut dyn RenderGame>,
+        config: &SearchConfig,
+        kill_signal: &mut BusReader<()>,
+        reporter: &mut impl StateReporter,
+    ) -> GameState {
+        // let mut stdin_key = stdin().keys();
+        let mut curr_heap = FixedHeap::new(HEAP_SIZE);
+        let mut next_heap = FixedHeap::new(HEAP_SIZE);
+        let mut scheduler = BeamScheduler::new(config, HEAP_SIZE);
+
+        let mut rng = Self::search_rng(config.seed);
+        let mut beam_reported_at = None::<Instant>;
+
+        let mut candidates: [Candidate; MAX_PLACEMENTS] =
+            array_init::array_init(|_| Candidate::default());
+        let mut children = Vec::new();
+        let mut backtrack = Backtrack::new();
+        for state in initial_states {
+            next_heap.push(state);
+        }
+        if next_heap.is_empty() {
+            next_heap.push(GameState::initial_state());
+        }
+        while !next_heap.is_empty() {
+            mem::swap(&mut curr_heap, &mut next_heap);
+            next_heap.clear();
+
+            let best = curr_heap.max().unwrap();
+            if reporter.report(best).is_err() {
+                return best.clone();
+            }
+            Self::report_beam(curr_heap.as_slice(), &mut beam_reported_at, reporter);
+            if BRANCH_DEPTHS.contains(&best.brick_count) {
+                let mut elites = curr_heap.iter().collect::<Vec<_>>();
+                elites.sort_by_key(|state| std::cmp::Reverse(state.sp_score));
+                elites.truncate(ELITES_REPORTED);
+                reporter.report_elites(&elites).ok();
+            }
+
+            if let Some(renderer) = renderer.as_deref_mut() {
+                renderer.render_game(best);
+                renderer.flush();
+            }
+            if best.brick_count >= MAX_BRICKS_COUNT || kill_signal.try_recv().is_ok() {
+                return best.clone();
+            }
+            if let Some(state) = Self::try_endgame(curr_heap.as_slice(), config, &scheduler, reporter) {
+                if let Some(renderer) = renderer.as_deref_mut() {
+                    renderer.render_game(&state);
+                    renderer.flush();
                 }
+                return state;
             }
-        });
+            backtrack.save(curr_heap.as_slice());
 
-        let kill_rx = kill_bus.add_rx();
-        thread::spawn(move || {
-            Self::spawn_thread(resource_receiver, resource_sender, result_snd, kill_rx);
-        });
+            next_heap.set_capacity(scheduler.next_width(MAX_BRICKS_COUNT - best.brick_count, best.grids.stack_height()));
+            let (expand_start, cpu_start) = (Instant::now(), thread_cpu_time());
+            match config.selection {
+                Selection::Jitter => {
+                    Self::expand_layer(curr_heap.as_slice(), &mut next_heap, &mut candidates, &mut rng)
+                }
+                selection => {
+                    children.clear();
+                    Self::collect_children(curr_heap.as_slice(), 0, &mut children, &mut candidates, selection, &mut rng);
+                    Self::push_selected(curr_heap.as_slice(), &mut children, &mut next_heap, selection, &mut rng);
+                }
+            }
+            scheduler.record_layer(curr_heap.len(), expand_start.elapsed(), thread_cpu_time() - cpu_start);
 
-        (kill_bus, render_handle)
+            if next_heap.is_empty() {
+                let beam = match backtrack.restore(curr_heap.as_slice(), &mut scheduler, reporter) {
+                    Some(beam) => beam,
+                    None => return Backtrack::best_reached(curr_heap.as_slice()),
+                };
+                next_heap.set_capacity(HEAP_SIZE);
+                for state in beam {
+                    next_heap.push(state);
+                }
+                rng = StdRng::seed_from_u64(rng.gen());
+            }
+        }
+
+        curr_heap.max().unwrap().clone()
     }
-    pub fn spawn_thread(
-        res_receiver: Receiver<BusReader<()>>,
-        res_sender: Sender<BusReader<()>>,
-        result_sender: Sender<GameState>,
-        mut kill_rx: BusReader<()>,
-    ) {
-        while let Ok(mut kill_rcv) = res_receiver.recv() {
-            if kill_rx.try_recv().is_ok() {
-                return;
+
+    /// Search with one beam of up to `COOP_HEAP_SIZE` states. Each layer the
+    /// beam is split into `threads` partitions that are expanded in parallel
+    /// into per-thread top-K heaps, which are then merged into the next beam.
+    /// Each of those keeps a bit more than its share of the beam width, see
+    /// `COOP_WORKER_SHARE`.
+    pub fn start_cooperative(
+        threads: usize,
+        config: &SearchConfig,
+        kill_signal: &mut BusReader<()>,
+        reporter: &mut impl StateReporter,
+    ) -> GameState {
+        let threads = threads.max(1);
+        let mut curr_heap = FixedHeap::new(COOP_HEAP_SIZE);
+        let mut next_heap = FixedHeap::new(COOP_HEAP_SIZE);
+        let mut workers = (0..threads)
+            .map(|i| CoopWorker::new(config.seed.map(|seed| seed.wrapping_add(i as u64))))
+            .collect::<Vec<_>>();
+        let mut scheduler = BeamScheduler::new(config, COOP_HEAP_SIZE);
+        let mut beam_reported_at = None::<Instant>;
+        let mut backtrack = Backtrack::new();
+        let mut children = Vec::new();
+
+        next_heap.push(GameState::initial_state());
+        while !next_heap.is_empty() {
+            mem::swap(&mut curr_heap, &mut next_heap);
+            next_heap.clear();
+
+            let best = curr_heap.max().unwrap();
+            if reporter.report(best).is_err() {
+                return best.clone();
             }
-            let res_sender = res_sender.clone();
-            let result_sender = result_sender.clone();
-            thread::spawn(move || {
-                let final_state = Self::start(false, &mut kill_rcv, result_sender.clone());
-                result_sender.send(final_state).ok();
-                res_sender.send(kill_rcv).ok();
+            Self::report_threads(curr_heap.as_slice(), threads, &mut beam_reported_at, reporter);
+
+            if best.brick_count >= MAX_BRICKS_COUNT || kill_signal.try_recv().is_ok() {
+                return best.clone();
+            }
+            if let Some(state) = Self::try_endgame(curr_heap.as_slice(), config, &scheduler, reporter) {
+                return state;
+            }
+            backtrack.save(curr_heap.as_slice());
+
+            let width = scheduler.next_width(MAX_BRICKS_COUNT - best.brick_count, best.grids.stack_height());
+            next_heap.set_capacity(width);
+            let worker_width = (width.div_ceil(threads) * COOP_WORKER_SHARE / 100).min(width);
+            let (expand_start, cpu_start) = (Instant::now(), thread_cpu_time());
+            let chunk_size = curr_heap.len().div_ceil(threads);
+            let selection = config.selection;
+            // Each thread returns the CPU time it spent.
+            let workers_cpu = thread::scope(|scope| {
+                let chunks = curr_heap.as_slice().chunks(chunk_size).enumerate();
+                let handles = workers
+                    .iter_mut()
+                    .zip(chunks)
+                    .map(|(worker, (idx, chunk))| {
+                        scope.spawn(move || {
+                            let cpu_start = thread_cpu_time();
+                            match selection {
+                                Selection::Jitter => {
+                                    worker.heap.clear();
+                                    worker.heap.set_capacity(worker_width);
+                                    Self::expand_layer(chunk, &mut worker.heap, &mut worker.candidates, &mut worker.rng);
+                                }
+                                selection => {
+                                    worker.children.clear();
+                                    let first_parent = idx * chunk_size;
+                                    Self::collect_children(
+                                        chunk,
+                                        first_parent,
+                                        &mut worker.children,
+                                        &mut worker.candidates,
+                                        selection,
+                                        &mut worker.rng,
+                                    );
+                                }
+                            }
+                            thread_cpu_time() - cpu_start
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles.into_iter().map(|handle| handle.join().unwrap()).sum::<Duration>()
             });
+            match selection {
+                Selection::Jitter => {
+                    for worker in &mut workers {
+                        for state in worker.heap.drain() {
+                            next_heap.push(state);
+                        }
+                    }
+                }
+                selection => {
+                    children.clear();
+                    for worker in &mut workers {
+                        children.append(&mut worker.children);
+                    }
+                    let rng = &mut workers[0].rng;
+                    Self::push_selected(curr_heap.as_slice(), &mut children, &mut next_heap, selection, rng);
+                }
+            }
+            scheduler.add_helper_cpu(workers_cpu);
+            let cpu = workers_cpu + (thread_cpu_time() - cpu_start);
+            scheduler.record_layer(curr_heap.len(), expand_start.elapsed(), cpu);
+
+            if next_heap.is_empty() {
+                let beam = match backtrack.restore(curr_heap.as_slice(), &mut scheduler, reporter) {
+                    Some(beam) => beam,
+                    None => return Backtrack::best_reached(curr_heap.as_slice()),
+                };
+                next_heap.set_capacity(COOP_HEAP_SIZE);
+                for state in beam {
+                    next_heap.push(state);
+                }
+                for worker in &mut workers {
+                    worker.rng = StdRng::seed_from_u64(worker.rng.gen());
+                }
+            }
+        }
+
+        curr_heap.max().unwrap().clone()
+    }
+
+    /// Report a summary of `beam` if the last one is older than `BEAM_REPORT_INTERVAL`.
+    fn report_beam(beam: &[GameState], reported_at: &mut Option<Instant>, reporter: &mut impl StateReporter) {
+        if reported_at.is_some_and(|at| at.elapsed() < BEAM_REPORT_INTERVAL) {
+            return;
+        }
+        *reported_at = Some(Instant::now());
+        reporter.report_beam(&BeamStats::from_beam(beam, TOP_K)).ok();
+    }
+
+    /// Report the chunks `start_cooperative` splits `beam` into for `threads`
+    /// threads, if the last report is older than `BEAM_REPORT_INTERVAL`.
+    fn report_threads(
+        beam: &[GameState],
+        threads: usize,
+        reported_at: &mut Option<Instant>,
+        reporter: &mut impl StateReporter,
+    ) {
+        if reported_at.is_some_and(|at| at.elapsed() < BEAM_REPORT_INTERVAL) {
+            return;
+        }
+        *reported_at = Some(Instant::now());
+        for (thread, chunk) in beam.chunks(beam.len().div_ceil(threads)).enumerate() {
+            let best = chunk.iter().max_by_key(|state| state.sp_score).unwrap();
+            reporter.report_thread(thread, best, &BeamStats::from_beam(chunk, TOP_K)).ok();
         }
     }
-    pub fn start(enable_render: bool, kill_signal: &mut BusReader<()>, result_sender: Sender<GameState>) -> GameState {
-        // let mut stdin_key = stdin().keys();
-        let mut curr_heap = FixedHeap::<GameState, HEAP_SIZE>::default();
-        let mut next_heap = FixedHeap::<GameState, HEAP_SIZE>::default();
 
-        let mut renderer = GameRenderer::new();
+    /// Once the beam is within `config.endgame_depth` bricks of the end and the
+    /// exhaustive endgame search of the remaining bricks fits the time left,
+    /// finish the game with it and report the result.
+    fn try_endgame(
+        beam: &[GameState],
+        config: &SearchConfig,
+        scheduler: &BeamScheduler,
+        reporter: &mut impl StateReporter,
+    ) -> Option<GameState> {
+        let remaining_bricks = MAX_BRICKS_COUNT.saturating_sub(beam.first()?.brick_count);
+        if remaining_bricks == 0 || remaining_bricks > config.endgame_depth {
+            return None;
+        }
+        let time = scheduler.remaining().unwrap_or(UNLIMITED_ENDGAME_TIME);
+        if endgame::estimate(beam, remaining_bricks) > time {
+            return None;
+        }
+        let state = endgame::solve_beam(beam, remaining_bricks)?;
+        reporter.report(&state).ok();
+        Some(state)
+    }
 
-        let mut rng = rand::thread_rng();
+    /// Suggest where to place the next brick of `state`: a beam of `width`
+    /// states is searched `depth` bricks ahead and the child of `state` leading
+    /// to the best one by `sp_score` is returned. `None` if no brick fits.
+    pub fn suggest(state: &GameState, depth: usize, width: usize) -> Option<GameState> {
+        let best = Self::search_ahead(state, depth, width)?;
+        let mut child = state.clone();
+        child.apply_placement(best.brick_stack[state.brick_stack.len()]);
+        Some(child)
+    }
 
-        let mut next_states: [GameState; EXPAND_SIZE] =
-            array_init::array_init(|_| GameState::initial_state());
-        let initial_state = GameState::initial_state();
-        next_heap.push(initial_state);
-        while next_heap.len() > 0 {
+    /// The best state by `sp_score` in the deepest layer a beam of `width`
+    /// states reaches from `state` in up to `depth` bricks. `None` if no
+    /// brick fits.
+    pub fn search_ahead(state: &GameState, depth: usize, width: usize) -> Option<GameState> {
+        let mut curr_heap = FixedHeap::new(width);
+        let mut next_heap = FixedHeap::new(width);
+        let mut candidates: [Candidate; MAX_PLACEMENTS] =
+            array_init::array_init(|_| Candidate::default());
+        let mut spare = GameState::default();
+        let mut best_leaf = None::<GameState>;
+        next_heap.push(state.clone());
+
+        for _ in 0..depth.max(1) {
             mem::swap(&mut curr_heap, &mut next_heap);
             next_heap.clear();
+            for curr_state in curr_heap.iter() {
+                let len = curr_state.candidates(&mut candidates);
+                Self::push_children(curr_state, &candidates[..len], &mut next_heap, &mut spare);
+            }
+            match next_heap.iter().max_by_key(|state| state.sp_score) {
+                Some(best) => best_leaf = Some(best.clone()),
+                None => break,
+            }
+        }
+        best_leaf
+    }
 
-            result_sender.send(curr_heap.peak().unwrap().clone()).unwrap();
+    /// A short deterministic run of the search for profile-guided builds, see
+    /// build.env: `bricks` layers of a beam of `width` states with a fixed
+    /// seed, then the endgame search on the result.
+    pub fn train_profile(bricks: usize, width: usize, seed: u64) -> GameState {
+        let config = SearchConfig {
+            min_beam_width: width,
+            max_beam_width: width,
+            endgame_depth: 0,
+            seed: Some(seed),
+            ..SearchConfig::default()
+        };
+        let mut kill_bus = Bus::new(1);
+        let state = Self::start(None, &config, &mut kill_bus.add_rx(), &mut DepthLimit(bricks));
+        endgame::solve(&state, PROFILE_ENDGAME_DEPTH)
+    }
 
-            if enable_render {
-                renderer.render_game(curr_heap.peak().unwrap());
-                renderer.flush();
+    fn search_rng(seed: Option<u64>) -> StdRng {
+        match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Expand every state in `states` and push the jittered children into `next_heap`.
+    fn expand_layer(
+        states: &[GameState],
+        next_heap: &mut FixedHeap<GameState>,
+        candidates: &mut [Candidate; MAX_PLACEMENTS],
+        rng: &mut impl Rng,
+    ) {
+        let mut spare = GameState::default();
+        for curr_state in states {
+            let len = curr_state.candidates(candidates);
+            for candidate in &mut candidates[..len] {
+                Self::jitter(candidate, rng);
             }
-            if curr_heap.peak().unwrap().brick_count >= MAX_BRICKS_COUNT
-                || kill_signal.try_recv().is_ok()
-            {
-                return curr_heap.peak().unwrap().clone();
-            }
-
-            for curr_state in &curr_heap {
-                let len = curr_state.next(&mut next_states);
-                for next_state in &mut next_states[..len] {
-                    next_state.sp_score += (rng.gen_range(-JITTER_RATE..JITTER_RATE)
-                        * next_state.sp_score as f64)
-                        as i32;
-                    let mut temp = GameState::default(); // temp=0, next=full
-                    mem::swap(&mut temp, next_state); // temp=full, next=0
-                    match next_heap.push(temp) {
-                        Some(mut old_state) => mem::swap(next_state, &mut old_state), // old=0, next=full
-                        None => *next_state = GameState::initial_state(),
-                    }
+            Self::push_children(curr_state, &candidates[..len], next_heap, &mut spare);
+        }
+    }
+
+    fn jitter(candidate: &mut Candidate, rng: &mut impl Rng) {
+        candidate.sp_score += (rng.gen_range(-JITTER_RATE..JITTER_RATE)
+            * candidate.sp_score as f64)
+            as i32;
+    }
+
+    /// Score every child of `states` into `children`, for a `selection` other
+    /// than `Selection::Jitter`. `states` start at index `first_parent` of
+    /// their layer.
+    fn collect_children(
+        states: &[GameState],
+        first_parent: usize,
+        children: &mut Vec<Child>,
+        candidates: &mut [Candidate; MAX_PLACEMENTS],
+        selection: Selection,
+        rng: &mut impl Rng,
+    ) {
+        for (idx, curr_state) in states.iter().enumerate() {
+            let len = curr_state.candidates(candidates);
+            for candidate in &mut candidates[..len] {
+                if selection.jitters() {
+                    Self::jitter(candidate, rng);
                 }
+                children.push(Child {
+                    parent: first_parent + idx,
+                    candidate: candidate.clone(),
+                });
             }
         }
+    }
 
-        curr_heap.peak().unwrap().clone()
+    /// Build the `children` of `states` that `selection` keeps into the
+    /// empty `next_heap`, as many as it has capacity for.
+    fn push_selected(
+        states: &[GameState],
+        children: &mut [Child],
+        next_heap: &mut FixedHeap<GameState>,
+        selection: Selection,
+        rng: &mut impl Rng,
+    ) {
+        let len = selection::select(children, next_heap.capacity(), selection, rng);
+        let mut spare = GameState::default();
+        for child in &children[..len] {
+            states[child.parent].child_into(&child.candidate, &mut spare);
+            if let Some(evicted) = next_heap.push(mem::take(&mut spare)) {
+                spare = evicted;
+            }
+        }
+    }
+
+    /// Push the children of `state` reached by `candidates` into `next_heap`.
+    /// A child is only built if its `sp_score` beats the minimum of a full
+    /// heap, in `spare` which then takes the allocation of the evicted state.
+    fn push_children(
+        state: &GameState,
+        candidates: &[Candidate],
+        next_heap: &mut FixedHeap<GameState>,
+        spare: &mut GameState,
+    ) {
+        for candidate in candidates {
+            if next_heap
+                .min_threshold()
+                .is_some_and(|min| candidate.sp_score <= min.sp_score)
+            {
+                continue;
+            }
+            state.child_into(candidate, spare);
+            if let Some(evicted) = next_heap.push(mem::take(spare)) {
+                *spare = evicted;
+            }
+        }
     }
 
     pub fn save_result(state: &GameState) {
@@ -124,3 +626,184 @@ impl TetrisAuto {
         std::fs::write("./op_sequence", op_str).unwrap();
     }
 }
+
+struct CoopWorker {
+    heap: FixedHeap<GameState>,
+    candidates: [Candidate; MAX_PLACEMENTS],
+    children: Vec<Child>,
+    rng: StdRng,
+}
+
+impl CoopWorker {
+    fn new(seed: Option<u64>) -> Self {
+        Self {
+            heap: FixedHeap::new(0),
+            candidates: array_init::array_init(|_| Candidate::default()),
+            children: Vec::new(),
+            rng: TetrisAuto::search_rng(seed),
+        }
+    }
+}
+
+/// Beams saved every `SAVE_INTERVAL` bricks, for a search to go back to when
+/// its beam dies out.
+struct Backtrack {
+    saved: VecDeque<Vec<GameState>>,
+    /// Bricks placed by the beam of the deepest dead end.
+    deepest: usize,
+    /// Dead ends since `deepest` changed.
+    dead_ends: usize,
+    /// Bricks placed by the beam restored last.
+    restored: Option<usize>,
+}
+
+impl Backtrack {
+    fn new() -> Self {
+        Self {
+            saved: VecDeque::with_capacity(SAVED_BEAMS + 1),
+            deepest: 0,
+            dead_ends: 0,
+            restored: None,
+        }
+    }
+
+    /// Keep a copy of `beam` if it is the first one or a multiple of
+    /// `SAVE_INTERVAL` bricks deep, at most its `HEAP_SIZE` best states.
+    fn save(&mut self, beam: &[GameState]) {
+        let brick_count = match beam.first() {
+            Some(state) => state.brick_count,
+            None => return,
+        };
+        if let Some(last) = self.saved.back() {
+            if brick_count % SAVE_INTERVAL != 0 || last[0].brick_count == brick_count {
+                return;
+            }
+        }
+        let mut saved = beam.to_vec();
+        if saved.len() > HEAP_SIZE {
+            saved.select_nth_unstable_by_key(HEAP_SIZE, |state| Reverse(state.sp_score));
+            saved.truncate(HEAP_SIZE);
+        }
+        self.saved.push_back(saved);
+        if self.saved.len() > SAVED_BEAMS {
+            self.saved.pop_front();
+        }
+    }
+
+    /// The saved beam to continue from after no state of `beam` could place
+    /// the next brick, `None` after `MAX_RETRIES` that got no further or once
+    /// the budget of `scheduler` is spent. The minimum width of `scheduler`
+    /// is widened for the retry, and `reporter` is told either way.
+    fn restore(
+        &mut self,
+        beam: &[GameState],
+        scheduler: &mut BeamScheduler,
+        reporter: &mut impl StateReporter,
+    ) -> Option<Vec<GameState>> {
+        let brick_count = beam[0].brick_count;
+        if brick_count > self.deepest {
+            self.deepest = brick_count;
+            self.dead_ends = 0;
+        }
+        self.dead_ends += 1;
+        // Dying again before a newer beam was saved makes the restored one a
+        // dead end as well, go back one more.
+        if self.saved.len() > 1 && self.restored == self.saved.back().map(|saved| saved[0].brick_count) {
+            self.saved.pop_back();
+        }
+        let restart = self
+            .saved
+            .back()
+            .filter(|_| self.dead_ends <= MAX_RETRIES && !scheduler.past_deadline())
+            .cloned();
+        let dead_end = DeadEnd {
+            brick_count,
+            attempt: self.dead_ends,
+            restart: restart.as_ref().map(|saved| {
+                (saved[0].brick_count, scheduler.widen(MAX_BRICKS_COUNT - saved[0].brick_count))
+            }),
+        };
+        reporter.report_dead_end(&dead_end).ok();
+        self.restored = dead_end.restart.map(|(brick_count, _)| brick_count);
+        restart
+    }
+
+    /// The state with the highest score of the last beam of a search that
+    /// gave up.
+    fn best_reached(beam: &[GameState]) -> GameState {
+        beam.iter().max_by_key(|state| state.score).unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bus::Bus;
+
+    use crate::{
+        budget::{BeamScheduler, SearchBudget, SearchConfig},
+        game::{GameState, ENDGAME_WINDOW, MAX_BRICKS_COUNT, MAX_PLACEMENTS},
+    };
+
+    use super::{DepthLimit, TetrisAuto, HEAP_SIZE};
+
+    #[test]
+    fn test_returns_best_state() {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let len = GameState::initial_state().next(&mut next_states);
+        let children = next_states[..len].to_vec();
+        let best = children.iter().map(|state| state.sp_score).max().unwrap();
+        assert!(children.iter().any(|state| state.sp_score < best));
+        let config = SearchConfig::default();
+
+        // Stopped by the reporter.
+        let mut kill_bus = Bus::<()>::new(1);
+        let state = TetrisAuto::start_from(children.clone(), None, &config, &mut kill_bus.add_rx(), &mut DepthLimit(1));
+        assert_eq!(state.sp_score, best);
+
+        // Killed.
+        let mut kill_rx = kill_bus.add_rx();
+        kill_bus.broadcast(());
+        let state = TetrisAuto::start_from(children, None, &config, &mut kill_rx, &mut DepthLimit(usize::MAX));
+        assert_eq!(state.sp_score, best);
+    }
+
+    #[test]
+    fn test_budget_ends_search() {
+        // The default minimum width can't finish a game in this budget, the
+        // rest is played greedily once it is spent.
+        let config = SearchConfig {
+            budget: SearchBudget::WallClock(Duration::from_millis(200)),
+            endgame_depth: 0,
+            seed: Some(1),
+            ..SearchConfig::default()
+        };
+        let mut kill_bus = Bus::<()>::new(1);
+        let state = TetrisAuto::start(None, &config, &mut kill_bus.add_rx(), &mut DepthLimit(usize::MAX));
+        assert!(state.brick_count > 0);
+    }
+
+    #[test]
+    fn test_endgame_fits_time() {
+        let config = SearchConfig::default();
+        let scheduler = BeamScheduler::new(&config, HEAP_SIZE);
+        let mut state = GameState::initial_state();
+        state.brick_count = MAX_BRICKS_COUNT - 2;
+        let solved = TetrisAuto::try_endgame(&[state.clone()], &config, &scheduler, &mut DepthLimit(usize::MAX));
+        assert_eq!(solved.unwrap().brick_count, MAX_BRICKS_COUNT);
+
+        // Too deep to search exhaustively in any time.
+        state.brick_count = MAX_BRICKS_COUNT - ENDGAME_WINDOW;
+        assert!(TetrisAuto::try_endgame(&[state.clone()], &config, &scheduler, &mut DepthLimit(usize::MAX)).is_none());
+
+        // No time left.
+        let config = SearchConfig {
+            budget: SearchBudget::WallClock(Duration::ZERO),
+            ..SearchConfig::default()
+        };
+        let scheduler = BeamScheduler::new(&config, HEAP_SIZE);
+        state.brick_count = MAX_BRICKS_COUNT - 2;
+        assert!(TetrisAuto::try_endgame(&[state], &config, &scheduler, &mut DepthLimit(usize::MAX)).is_none());
+    }
+}
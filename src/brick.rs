@@ -113,9 +113,35 @@ lazy_static::lazy_static!{
 
         bricks_top
     };
+
+    /// The cells of a brick as rows of the `GameGrids` layout for every
+    /// center column, the top row of the brick in the lowest 16 bits. 0 where
+    /// a cell would leave the board.
+    pub static ref BRICKS_MASK: [[[u64; 10]; 4]; 7] = {
+        let mut bricks_mask = [[[0; 10]; 4]; 7];
+
+        for shape in 0..7 {
+            for state in 0..4 {
+                for x in 0..10 {
+                    let cells = &BRICKS_CONFIG[shape][state];
+                    if cells.iter().any(|pos| !(0..10).contains(&(pos.0 + x))) {
+                        continue;
+                    }
+                    let top = BRICKS_TOP[shape][state];
+                    for pos in cells {
+                        bricks_mask[shape][state][x as usize] |= 1 << ((pos.1 - top) * 16 + pos.0 + x);
+                    }
+                }
+            }
+        }
+
+        bricks_mask
+    };
 }
 
 #[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "(usize, usize)"))]
 pub struct Brick(pub BrickType, pub BrickState);
 
 impl Brick {
@@ -145,6 +171,11 @@ impl Brick {
         Self(self.0, (self.1 + rot) % 4)
     }
 
+    /// The letter of the shape, one of `ILJTOSZ`.
+    pub fn shape_name(&self) -> char {
+        b"ILJTOSZ"[self.0] as char
+    }
+
     #[inline(always)]
     pub fn state_count(&self) -> usize {
         match self.0 {
@@ -183,4 +214,14 @@ impl Brick {
     pub fn get_top_pos(&self, y: i8) -> i8 {
         BRICKS_TOP[self.0][self.1] + y
     } 
+
+    /// The brick centered in column `x` as a window of rows from
+    /// `GameGrids::rows_window`, 0 if it does not fit in the board.
+    #[inline(always)]
+    pub fn row_mask(&self, x: i8) -> u64 {
+        match x {
+            0..=9 => BRICKS_MASK[self.0][self.1][x as usize],
+            _ => 0,
+        }
+    }
 }
@@ -0,0 +1,317 @@
+use std::time::{Duration, Instant};
+
+use crate::{endgame::DEFAULT_ENDGAME_DEPTH, grid::GRID_HEIGHT, selection::Selection};
+
+/// Assumed cost of expanding one beam state before anything has been measured.
+const INITIAL_STATE_COST: f64 = 20e-6;
+/// Weight of the newest measurement in the moving average of the per-state cost.
+const COST_SMOOTHING: f64 = 0.1;
+/// Time reserved to finish the game at minimal width is over-estimated by this factor.
+const RESERVE_FACTOR: f64 = 2.0;
+/// Beam width once the budget is spent, a greedy pass that plays the rest of
+/// the game quickly.
+const DEADLINE_WIDTH: usize = 1;
+const MIN_RISK: f64 = 0.5;
+const MAX_RISK: f64 = 2.5;
+/// Default `min_beam_width`. Narrower beams die out sooner, a width of 1000
+/// typically dies around brick 4500. This one can die out as well, the search
+/// then retries from a saved beam with a wider minimum.
+pub const DEFAULT_MIN_BEAM_WIDTH: usize = 2000;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SearchBudget {
+    Unlimited,
+    /// Real time elapsed since the search started.
+    WallClock(Duration),
+    /// CPU time used by the search thread and the threads helping it, like
+    /// those of a cooperative search. See `thread_cpu_time` for platforms
+    /// without a thread CPU clock.
+    Cpu(Duration),
+}
+
+/// CPU time used by the calling thread so far.
+#[cfg(target_family = "unix")]
+pub fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // Reading the clock of the calling thread can't fail.
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// Without a thread CPU clock, the wall-clock time since the first call.
+#[cfg(not(target_family = "unix"))]
+pub fn thread_cpu_time() -> Duration {
+    lazy_static::lazy_static! {
+        static ref STARTED: Instant = Instant::now();
+    }
+    STARTED.elapsed()
+}
+
+/// `min_beam_width` has to be wide enough for the heuristic to survive to the
+/// last brick, the scheduler only guarantees the time to finish at that width.
+/// A `max_beam_width` below it lowers the minimum to match.
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    pub budget: SearchBudget,
+    pub min_beam_width: usize,
+    pub max_beam_width: usize,
+    /// Up to the last `endgame_depth` bricks are placed by an exhaustive
+    /// search for the highest realized score, as many as the time left allows,
+    /// see `endgame::estimate`.
+    pub endgame_depth: usize,
+    /// Seed of the score jitter, a random one is used when `None`.
+    pub seed: Option<u64>,
+    /// How each layer of the beam is chosen among the children of the last.
+    pub selection: Selection,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            budget: SearchBudget::Unlimited,
+            min_beam_width: DEFAULT_MIN_BEAM_WIDTH,
+            max_beam_width: usize::MAX,
+            endgame_depth: DEFAULT_ENDGAME_DEPTH,
+            seed: None,
+            selection: Selection::Jitter,
+        }
+    }
+}
+
+/// Spreads the search budget across the remaining layers by choosing the
+/// beam width of each layer.
+///
+/// It keeps enough time in reserve to finish the remaining bricks at
+/// `min_beam_width` if the budget allows it. Past the deadline the rest of the
+/// game is played greedily.
+pub struct BeamScheduler {
+    budget: SearchBudget,
+    min_width: usize,
+    max_width: usize,
+    started: Instant,
+    /// `thread_cpu_time` of the search thread when the scheduler was created.
+    cpu_started: Duration,
+    /// CPU time used by threads other than the search thread.
+    helper_cpu: Duration,
+    state_cost: f64,
+}
+
+impl BeamScheduler {
+    /// Has to be created on the thread running the search. `capacity` is the
+    /// size of the heaps holding the beam, no layer is wider.
+    pub fn new(config: &SearchConfig, capacity: usize) -> Self {
+        let max_width = config.max_beam_width.min(capacity).max(1);
+        Self {
+            budget: config.budget,
+            min_width: config.min_beam_width.clamp(1, max_width),
+            max_width,
+            started: Instant::now(),
+            cpu_started: thread_cpu_time(),
+            helper_cpu: Duration::ZERO,
+            state_cost: INITIAL_STATE_COST,
+        }
+    }
+
+    /// Record that expanding `states` beam states took `elapsed` of real time
+    /// and `cpu` of CPU time over all threads.
+    pub fn record_layer(&mut self, states: usize, elapsed: Duration, cpu: Duration) {
+        let elapsed = match self.budget {
+            SearchBudget::Cpu(_) => cpu,
+            _ => elapsed,
+        };
+        if states > 0 {
+            let cost = elapsed.as_secs_f64() / states as f64;
+            self.state_cost += (cost - self.state_cost) * COST_SMOOTHING;
+        }
+    }
+
+    /// Count `cpu` of CPU time another thread spent on the search.
+    pub fn add_helper_cpu(&mut self, cpu: Duration) {
+        self.helper_cpu += cpu;
+    }
+
+    /// Budget used so far, only valid on the search thread.
+    pub fn used(&self) -> Duration {
+        match self.budget {
+            SearchBudget::Cpu(_) => thread_cpu_time().saturating_sub(self.cpu_started) + self.helper_cpu,
+            _ => self.started.elapsed(),
+        }
+    }
+
+    /// Time left before the deadline, `None` when the budget is unlimited.
+    pub fn remaining(&self) -> Option<Duration> {
+        match self.budget {
+            SearchBudget::Unlimited => None,
+            SearchBudget::WallClock(limit) | SearchBudget::Cpu(limit) => {
+                Some(limit.saturating_sub(self.used()))
+            }
+        }
+    }
+
+    /// Whether the budget is limited and spent.
+    pub fn past_deadline(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Beam width of the next layer.
+    ///
+    /// `remaining_bricks` is the depth still to be searched and `stack_height`
+    /// the height of the best board, a higher stack gets a wider beam.
+    pub fn next_width(&self, remaining_bricks: usize, stack_height: usize) -> usize {
+        let remaining = match self.remaining() {
+            None => return self.max_width,
+            Some(Duration::ZERO) => return DEADLINE_WIDTH,
+            Some(remaining) => remaining.as_secs_f64(),
+        };
+        if remaining_bricks == 0 {
+            return self.min_width;
+        }
+        let reserve =
+            remaining_bricks as f64 * self.min_width as f64 * self.state_cost * RESERVE_FACTOR;
+        let spendable = remaining - reserve;
+        if spendable <= 0.0 {
+            return self.min_width;
+        }
+        let extra_states = spendable / remaining_bricks as f64 / self.state_cost;
+        let width = self.min_width as f64 + extra_states * Self::risk(stack_height);
+        (width as usize).clamp(self.min_width, self.max_width)
+    }
+
+    /// Double the minimum width to retry a part of the game the beam died out
+    /// in, `remaining_bricks` before the end. It stays within the maximum width
+    /// and the width the remaining budget can finish the game at. Returns the
+    /// new minimum.
+    pub fn widen(&mut self, remaining_bricks: usize) -> usize {
+        let mut widest = self.max_width;
+        if let Some(remaining) = self.remaining() {
+            let cost = remaining_bricks.max(1) as f64 * self.state_cost * RESERVE_FACTOR;
+            widest = widest.min((remaining.as_secs_f64() / cost) as usize);
+        }
+        self.min_width = self.min_width.saturating_mul(2).min(widest.max(self.min_width));
+        self.min_width
+    }
+
+    fn risk(stack_height: usize) -> f64 {
+        let ratio = stack_height as f64 / GRID_HEIGHT as f64;
+        (MIN_RISK + ratio * ratio * MAX_RISK).clamp(MIN_RISK, MAX_RISK)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{thread_cpu_time, BeamScheduler, SearchBudget, SearchConfig, DEADLINE_WIDTH};
+
+    fn config(budget: SearchBudget) -> SearchConfig {
+        SearchConfig {
+            budget,
+            min_beam_width: 4,
+            max_beam_width: 20000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let scheduler = BeamScheduler::new(&config(SearchBudget::Unlimited), 10000);
+        assert_eq!(scheduler.next_width(100, 0), 10000);
+        assert!(scheduler.remaining().is_none());
+    }
+
+    #[test]
+    fn test_exhausted_budget() {
+        let mut scheduler = BeamScheduler::new(&config(SearchBudget::Cpu(Duration::from_millis(10))), 10000);
+        scheduler.add_helper_cpu(Duration::from_millis(5));
+        assert!(!scheduler.past_deadline());
+        assert_eq!(scheduler.next_width(100, 10), 4);
+        scheduler.add_helper_cpu(Duration::from_millis(20));
+        assert_eq!(scheduler.remaining(), Some(Duration::ZERO));
+        assert!(scheduler.past_deadline());
+        assert_eq!(scheduler.next_width(100, 10), DEADLINE_WIDTH);
+    }
+
+    #[test]
+    fn test_clocks() {
+        let mut scheduler = BeamScheduler::new(&config(SearchBudget::Cpu(Duration::from_secs(10))), 10000);
+        scheduler.add_helper_cpu(Duration::from_secs(1));
+        let remaining = scheduler.remaining().unwrap();
+        assert!(remaining <= Duration::from_secs(9) && remaining > Duration::from_secs(8), "{:?}", remaining);
+        // Waiting takes no CPU time.
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(remaining - scheduler.remaining().unwrap() < Duration::from_millis(100));
+
+        let mut scheduler = BeamScheduler::new(&config(SearchBudget::WallClock(Duration::from_secs(10))), 10000);
+        scheduler.add_helper_cpu(Duration::from_secs(1));
+        assert!(scheduler.remaining().unwrap() > Duration::from_secs(9));
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_thread_cpu_time() {
+        let start = thread_cpu_time();
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(thread_cpu_time() - start < Duration::from_millis(100));
+        // Spins until the thread has used 10ms of CPU time.
+        let spinning = std::time::Instant::now();
+        while thread_cpu_time() - start < Duration::from_millis(10) {
+            assert!(spinning.elapsed() < Duration::from_secs(60));
+        }
+    }
+
+    #[test]
+    fn test_risk_widens_beam() {
+        let mut scheduler = BeamScheduler::new(&config(SearchBudget::Cpu(Duration::from_secs(10))), 10000);
+        scheduler.record_layer(1000, Duration::from_millis(10), Duration::from_millis(10));
+        let low = scheduler.next_width(5000, 4);
+        let high = scheduler.next_width(5000, 16);
+        assert!(low >= 4 && low < high, "{} {}", low, high);
+    }
+
+    #[test]
+    fn test_reserve_grows_with_depth() {
+        let mut scheduler = BeamScheduler::new(&config(SearchBudget::Cpu(Duration::from_secs(1))), 10000);
+        scheduler.record_layer(1000, Duration::from_millis(10), Duration::from_millis(10));
+        assert!(scheduler.next_width(10, 10) > scheduler.next_width(10000, 10));
+    }
+
+    #[test]
+    fn test_widen() {
+        // Capped by the heap capacity.
+        let mut scheduler = BeamScheduler::new(
+            &SearchConfig {
+                min_beam_width: 3000,
+                ..config(SearchBudget::Unlimited)
+            },
+            10000,
+        );
+        assert_eq!(scheduler.widen(100), 6000);
+        assert_eq!(scheduler.widen(100), 10000);
+        assert_eq!(scheduler.widen(100), 10000);
+
+        // Capped by the width the budget can finish the game at: 10 bricks
+        // of 1000 states at 1ms each, twice over, take 20s.
+        let mut scheduler = BeamScheduler::new(
+            &SearchConfig {
+                min_beam_width: 600,
+                ..config(SearchBudget::Cpu(Duration::from_secs(20)))
+            },
+            10000,
+        );
+        scheduler.state_cost = 1e-3;
+        let widened = scheduler.widen(10);
+        assert!((990..=1000).contains(&widened), "{}", widened);
+        assert_eq!(scheduler.widen(10), widened);
+
+        // The minimum never exceeds the maximum.
+        let scheduler = BeamScheduler::new(
+            &SearchConfig {
+                max_beam_width: 500,
+                ..Default::default()
+            },
+            10000,
+        );
+        assert_eq!(scheduler.next_width(100, 10), 500);
+        assert_eq!(scheduler.min_width, 500);
+    }
+}
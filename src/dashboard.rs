@@ -0,0 +1,393 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auto::{DeadEnd, StateReporter},
+    game::GameState,
+    grid::{GameGrids, GRID_HEIGHT, GRID_WIDTH},
+    selection::{niche_key, Selection},
+    vec2::Vec2,
+};
+
+/// Number of beam boards shown next to the best board.
+pub const TOP_K: usize = 4;
+/// How often the dashboard is redrawn.
+pub const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// How often a search summarizes its beam, sorting it every layer would slow it down.
+pub const BEAM_REPORT_INTERVAL: Duration = Duration::from_millis(500);
+/// Bricks/s is measured over at least this long.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Summary of one beam layer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BeamStats {
+    pub width: usize,
+    pub min: i32,
+    pub median: i32,
+    pub max: i32,
+    /// Number of distinct column height profiles, see `selection::niche_key`.
+    pub profiles: usize,
+    /// Boards of the best states by `sp_score`, best first.
+    pub top: Vec<GameGrids>,
+}
+
+impl BeamStats {
+    pub fn from_beam(beam: &[GameState], k: usize) -> Self {
+        let mut ranked = beam.iter().collect::<Vec<_>>();
+        ranked.sort_by_key(|state| std::cmp::Reverse(state.sp_score));
+        let score = |idx: usize| ranked.get(idx).map_or(0, |state| state.sp_score);
+        Self {
+            width: beam.len(),
+            min: score(beam.len().saturating_sub(1)),
+            median: score(beam.len() / 2),
+            max: score(0),
+            profiles: beam.iter().map(|state| niche_key(&state.grids)).collect::<HashSet<_>>().len(),
+            top: ranked.iter().take(k).map(|state| state.grids.clone()).collect(),
+        }
+    }
+}
+
+/// Progress messages of the searches, indexed by worker.
+pub enum Progress {
+    Best(usize, GameState),
+    Beam(usize, BeamStats),
+    DeadEnd(usize, DeadEnd),
+}
+
+/// Reports the progress of the search run by `worker` to a dashboard thread.
+/// The threads of a cooperative search get a row each, numbered from 0 and
+/// sharing theirs with `worker`.
+pub struct ChannelReporter {
+    pub worker: usize,
+    pub sender: Sender<Progress>,
+}
+
+impl ChannelReporter {
+    fn send(&self, progress: Progress) -> io::Result<()> {
+        self.sender
+            .send(progress)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "dashboard closed"))
+    }
+}
+
+impl StateReporter for ChannelReporter {
+    fn report(&mut self, state: &GameState) -> io::Result<()> {
+        self.send(Progress::Best(self.worker, state.clone()))
+    }
+
+    fn report_beam(&mut self, stats: &BeamStats) -> io::Result<()> {
+        self.send(Progress::Beam(self.worker, stats.clone()))
+    }
+
+    fn report_dead_end(&mut self, dead_end: &DeadEnd) -> io::Result<()> {
+        self.send(Progress::DeadEnd(self.worker, *dead_end))
+    }
+
+    fn report_thread(&mut self, thread: usize, best: &GameState, stats: &BeamStats) -> io::Result<()> {
+        self.send(Progress::Best(thread, best.clone()))?;
+        self.send(Progress::Beam(thread, stats.clone()))
+    }
+}
+
+struct WorkerProgress {
+    depth: usize,
+    bricks_per_sec: f64,
+    measured_at: Instant,
+    measured_depth: usize,
+    beam: Option<BeamStats>,
+    dead_end: Option<DeadEnd>,
+}
+
+/// Live view of a running search: per-worker speed, the `sp_score` spread of
+/// the beams and the best boards, to tell whether a beam is collapsing.
+pub struct Dashboard {
+    started: Instant,
+    selection: Selection,
+    best: GameState,
+    workers: BTreeMap<usize, WorkerProgress>,
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            selection: Selection::default(),
+            best: GameState::default(),
+            workers: BTreeMap::new(),
+        }
+    }
+
+    /// Show `selection` as the policy choosing the beams.
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// The state with the highest score seen so far.
+    pub fn best(&self) -> &GameState {
+        &self.best
+    }
+
+    /// Keep `state` if it scores higher than the best so far, or is deeper at
+    /// the same score. Returns whether it was kept.
+    pub fn offer_best(&mut self, state: &GameState) -> bool {
+        if (state.score, state.brick_count) > (self.best.score, self.best.brick_count) {
+            self.best = state.clone();
+            return true;
+        }
+        false
+    }
+
+    /// Record the state at the top of the beam of `worker`.
+    pub fn update(&mut self, worker: usize, state: &GameState) {
+        self.update_at(worker, state, Instant::now());
+    }
+
+    fn update_at(&mut self, worker: usize, state: &GameState, now: Instant) {
+        self.offer_best(state);
+        let progress = self.workers.entry(worker).or_insert_with(|| WorkerProgress {
+            depth: state.brick_count,
+            bricks_per_sec: 0.0,
+            measured_at: now,
+            measured_depth: state.brick_count,
+            beam: None,
+            dead_end: None,
+        });
+        if state.brick_count < progress.depth {
+            // A new job started.
+            progress.measured_at = now;
+            progress.measured_depth = state.brick_count;
+        }
+        progress.depth = state.brick_count;
+        let elapsed = now.duration_since(progress.measured_at);
+        if elapsed >= RATE_WINDOW {
+            progress.bricks_per_sec =
+                (progress.depth - progress.measured_depth) as f64 / elapsed.as_secs_f64();
+            progress.measured_at = now;
+            progress.measured_depth = progress.depth;
+        }
+    }
+
+    pub fn update_beam(&mut self, worker: usize, stats: BeamStats) {
+        if let Some(progress) = self.workers.get_mut(&worker) {
+            progress.beam = Some(stats);
+        }
+    }
+
+    /// Record the last dead end of the beam of `worker`.
+    pub fn update_dead_end(&mut self, worker: usize, dead_end: DeadEnd) {
+        if let Some(progress) = self.workers.get_mut(&worker) {
+            progress.dead_end = Some(dead_end);
+        }
+    }
+
+    pub fn remove(&mut self, worker: usize) {
+        self.workers.remove(&worker);
+    }
+
+    pub fn apply(&mut self, progress: Progress) {
+        match progress {
+            Progress::Best(worker, state) => self.update(worker, &state),
+            Progress::Beam(worker, stats) => self.update_beam(worker, stats),
+            Progress::DeadEnd(worker, dead_end) => self.update_dead_end(worker, dead_end),
+        }
+    }
+
+    /// Render the dashboard as lines of text.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "Best score {}  sp_score {}  bricks {}  elapsed {:.1}s  selection {}",
+                self.best.score,
+                self.best.sp_score,
+                self.best.brick_count,
+                self.started.elapsed().as_secs_f64(),
+                self.selection
+            ),
+            String::new(),
+            "worker  depth  bricks/s   beam  profiles  sp_score min / median / max".to_string(),
+        ];
+        for (worker, progress) in &self.workers {
+            let beam = match &progress.beam {
+                Some(beam) => format!(
+                    "{:>6} {:>9}  {} / {} / {}",
+                    beam.width, beam.profiles, beam.min, beam.median, beam.max
+                ),
+                None => format!("{:>6}", "-"),
+            };
+            lines.push(format!(
+                "{:>6} {:>6} {:>9.1} {}",
+                worker, progress.depth, progress.bricks_per_sec, beam
+            ));
+        }
+        for (worker, progress) in &self.workers {
+            if let Some(dead_end) = &progress.dead_end {
+                lines.push(format!("worker {}: {}", worker, dead_end));
+            }
+        }
+        lines.push(String::new());
+
+        // The top boards of the beam with the highest `sp_score`.
+        let top = self
+            .workers
+            .values()
+            .filter_map(|progress| progress.beam.as_ref())
+            .max_by_key(|beam| beam.max)
+            .map_or(&[][..], |beam| &beam.top[..]);
+        let board = board_lines(&self.best.grids);
+        let minis = top.iter().map(mini_board_lines).collect::<Vec<_>>();
+        lines.push(format!("{:<14}{}", "Best board", if minis.is_empty() { "" } else { "Top beam boards" }));
+        for (row, board_line) in board.iter().enumerate() {
+            let mut line = format!("{:<14}", board_line);
+            for mini in &minis {
+                if let Some(mini_line) = mini.get(row) {
+                    line.push_str(mini_line);
+                    line.push(' ');
+                }
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        let heights = self.best.grids.column_heights().map(|height| height.to_string());
+        lines.push(format!("Column heights {}", heights.join(" ")));
+        lines
+    }
+}
+
+fn board_lines(grids: &GameGrids) -> Vec<String> {
+    let mut lines = (0..GRID_HEIGHT as i8)
+        .map(|y| {
+            let row = (0..GRID_WIDTH as i8)
+                .map(|x| if grids.get(Vec2(x, y)) { '#' } else { '.' })
+                .collect::<String>();
+            format!("|{}|", row)
+        })
+        .collect::<Vec<_>>();
+    lines.push(format!("+{}+", "-".repeat(GRID_WIDTH as usize)));
+    lines
+}
+
+/// A board at half height, each character covers two rows.
+fn mini_board_lines(grids: &GameGrids) -> Vec<String> {
+    (0..GRID_HEIGHT as i8)
+        .step_by(2)
+        .map(|y| {
+            let row = (0..GRID_WIDTH as i8)
+                .map(|x| match (grids.get(Vec2(x, y)), grids.get(Vec2(x, y + 1))) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect::<String>();
+            format!("|{}|", row)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::mpsc::channel,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        auto::{DeadEnd, StateReporter},
+        game::{GameState, MAX_PLACEMENTS},
+    };
+
+    use crate::selection::Selection;
+
+    use super::{BeamStats, ChannelReporter, Dashboard};
+
+    fn children() -> Vec<GameState> {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let len = GameState::initial_state().next(&mut next_states);
+        next_states.truncate(len);
+        next_states
+    }
+
+    #[test]
+    fn test_beam_stats() {
+        let mut beam = children();
+        for (i, state) in beam.iter_mut().enumerate() {
+            state.sp_score = i as i32 * 10;
+        }
+        let stats = BeamStats::from_beam(&beam, 2);
+        let n = beam.len() as i32;
+        assert_eq!(stats.width, beam.len());
+        assert_eq!((stats.min, stats.max), (0, (n - 1) * 10));
+        assert_eq!(stats.median, (n - 1 - n / 2) * 10);
+        assert_eq!(stats.top, vec![beam[beam.len() - 1].grids.clone(), beam[beam.len() - 2].grids.clone()]);
+        assert!(stats.profiles > 1 && stats.profiles <= beam.len());
+        assert_eq!(BeamStats::from_beam(&[], 2), BeamStats::default());
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut dashboard = Dashboard::new();
+        let start = Instant::now();
+        let mut state = GameState::initial_state();
+        dashboard.update_at(0, &state, start);
+        state.brick_count = 300;
+        dashboard.update_at(0, &state, start + Duration::from_secs(2));
+        assert_eq!(dashboard.workers[&0].bricks_per_sec, 150.0);
+        state.brick_count = 10;
+        dashboard.update_at(0, &state, start + Duration::from_secs(3));
+        assert_eq!(dashboard.workers[&0].measured_depth, 10);
+    }
+
+    #[test]
+    fn test_lines() {
+        let beam = children();
+        let mut dashboard = Dashboard::new().with_selection(Selection::Niche(4));
+        dashboard.update(3, &beam[0]);
+        dashboard.update_beam(3, BeamStats::from_beam(&beam, 2));
+        let lines = dashboard.lines();
+        assert!(lines[0].ends_with("selection niche:4"));
+        assert!(lines.iter().any(|line| line.trim_start().starts_with("3      1")));
+        assert!(lines.iter().any(|line| line.contains("Top beam boards")));
+        let heights = beam[0].grids.column_heights().map(|height| height.to_string());
+        assert!(lines.contains(&format!("Column heights {}", heights.join(" "))));
+    }
+
+    #[test]
+    fn test_thread_rows() {
+        let beam = children();
+        let (sender, receiver) = channel();
+        let mut reporter = ChannelReporter { worker: 0, sender };
+        for (thread, chunk) in beam.chunks(beam.len().div_ceil(3)).enumerate() {
+            reporter.report_thread(thread, &chunk[0], &BeamStats::from_beam(chunk, 2)).unwrap();
+        }
+        drop(reporter);
+        let mut dashboard = Dashboard::new();
+        receiver.into_iter().for_each(|progress| dashboard.apply(progress));
+        let widths = dashboard.workers.values().map(|progress| progress.beam.as_ref().unwrap().width);
+        assert_eq!(widths.sum::<usize>(), beam.len());
+        assert_eq!(dashboard.workers.keys().copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_dead_end_lines() {
+        let mut dashboard = Dashboard::new();
+        dashboard.update(1, &GameState::initial_state());
+        let dead_end = DeadEnd { brick_count: 450, attempt: 2, restart: Some((400, 512)) };
+        dashboard.update_dead_end(1, dead_end);
+        let line = "worker 1: beam died at brick 450, retry 2 from brick 400 with a new seed and width >= 512";
+        assert!(dashboard.lines().iter().any(|l| l == line));
+
+        dashboard.update_dead_end(1, DeadEnd { restart: None, ..dead_end });
+        let line = "worker 1: beam died at brick 450, no complete game after 1 retries";
+        assert!(dashboard.lines().iter().any(|l| l == line));
+    }
+}
@@ -0,0 +1,898 @@
+//! Coordinator/worker protocol over plain TCP.
+//!
+//! Every message is a single line of ASCII text:
+//!
+//! ```text
+//! coordinator -> worker
+//!   JOB <seed> <budget> <min_beam> <max_beam> <endgame> <selection> <n>
+//!                                                   followed by n `STATE` lines
+//!   STATE <brick_stack>                             one state of the starting beam
+//!   STOP                                            abort the running job and disconnect
+//! worker -> coordinator
+//!   BEST <score> <sp_score> <brick_count> <rand_num> <grid>
+//!   BEAM <width> <min> <median> <max> <profiles> <grid>...
+//!                                                   `sp_score` spread, distinct column height
+//!                                                   profiles and top boards of the beam
+//!   ELITE <brick_stack>                             a strong state at a branch depth
+//!   DEAD <brick_count> <attempt> <from> <width>     the beam died out, `<from>` and `<width>`
+//!                                                   are `-` if the worker gave up
+//!   RESULT <brick_stack>
+//!   ERROR <message>                                 the job can't be run, e.g. it has an
+//!                                                   invalid `STATE`
+//! ```
+//!
+//! `<budget>` is `unlimited`, `wall:<secs>` or `cpu:<secs>`, `<seed>` is `-`
+//! for a random seed and `<selection>` is a `Selection` such as `niche:4`.
+//! A `<brick_stack>` is written as 4 hex digits per entry and `<grid>` as the
+//! 5 words of `GameGrids` in hex joined by `,`.
+//! Results carry only the placements, the coordinator replays them to get the
+//! final state so a worker can't report a score it didn't play. A result with
+//! a placement the game doesn't allow is dropped and its worker disconnected,
+//! as is a worker that answers with `ERROR`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bus::{Bus, BusReader};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    archive::{EliteArchive, BRANCH_DEPTHS, ELITES_PER_DEPTH},
+    auto::{DeadEnd, StateReporter, TetrisAuto},
+    budget::{SearchBudget, SearchConfig},
+    dashboard::{BeamStats, Dashboard, REFRESH_INTERVAL},
+    game::{GameState, MAX_BRICKS_COUNT},
+    grid::GameGrids,
+    op::GameOPStr,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest message accepted, in bytes. A `RESULT` of a complete game takes
+/// about 40 kB.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+pub struct Job {
+    pub config: SearchConfig,
+    pub checkpoint: Vec<Vec<u16>>,
+}
+
+pub enum CoordinatorMessage {
+    Job(Job),
+    Stop,
+}
+
+pub enum WorkerMessage {
+    Best(GameState),
+    Beam(BeamStats),
+    Elite(Vec<u16>),
+    DeadEnd(DeadEnd),
+    Result(Vec<u16>),
+    Error(String),
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_field<T: std::str::FromStr>(field: Option<&str>, name: &str) -> io::Result<T> {
+    let field = field.ok_or_else(|| invalid_data(format!("missing field {}", name)))?;
+    field
+        .parse()
+        .map_err(|_| invalid_data(format!("invalid {}: {}", name, field)))
+}
+
+/// Read a line like `BufRead::read_line`, failing on lines longer than
+/// `MAX_LINE_LEN` instead of buffering them.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let len = reader.by_ref().take(MAX_LINE_LEN as u64 + 1).read_line(line)?;
+    if len > MAX_LINE_LEN {
+        return Err(invalid_data(format!("message longer than {} bytes", MAX_LINE_LEN)));
+    }
+    Ok(len)
+}
+
+pub fn encode_brick_stack(brick_stack: &[u16]) -> String {
+    brick_stack.iter().map(|placement| format!("{:04x}", placement)).collect()
+}
+
+pub fn decode_brick_stack(text: &str) -> io::Result<Vec<u16>> {
+    let text = text.trim();
+    if !text.len().is_multiple_of(4) {
+        return Err(invalid_data(format!("brick stack length {} is not a multiple of 4", text.len())));
+    }
+    (0..text.len())
+        .step_by(4)
+        .map(|i| {
+            u16::from_str_radix(&text[i..i + 4], 16)
+                .map_err(|_| invalid_data(format!("invalid brick stack entry: {}", &text[i..i + 4])))
+        })
+        .collect()
+}
+
+/// Read a checkpoint file, one encoded `brick_stack` per line. Every line has
+/// to replay as a game.
+pub fn load_checkpoint(path: &str) -> io::Result<Vec<Vec<u16>>> {
+    fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            let brick_stack = decode_brick_stack(line)?;
+            GameState::try_from_brick_stack(&brick_stack)
+                .map_err(|err| invalid_data(format!("line {}: {}", idx + 1, err)))?;
+            Ok(brick_stack)
+        })
+        .collect()
+}
+
+fn encode_budget(budget: SearchBudget) -> String {
+    match budget {
+        SearchBudget::Unlimited => "unlimited".to_string(),
+        SearchBudget::WallClock(limit) => format!("wall:{}", limit.as_secs_f64()),
+        SearchBudget::Cpu(limit) => format!("cpu:{}", limit.as_secs_f64()),
+    }
+}
+
+fn decode_budget(text: &str) -> io::Result<SearchBudget> {
+    let secs = |value: &str| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(Duration::from_secs_f64)
+            .ok_or_else(|| invalid_data(format!("invalid budget: {}", text)))
+    };
+    match text.split_once(':') {
+        None if text == "unlimited" => Ok(SearchBudget::Unlimited),
+        Some(("wall", value)) => Ok(SearchBudget::WallClock(secs(value)?)),
+        Some(("cpu", value)) => Ok(SearchBudget::Cpu(secs(value)?)),
+        _ => Err(invalid_data(format!("invalid budget: {}", text))),
+    }
+}
+
+pub fn write_job(writer: &mut impl Write, job: &Job) -> io::Result<()> {
+    let seed = match job.config.seed {
+        Some(seed) => seed.to_string(),
+        None => "-".to_string(),
+    };
+    let mut message = format!(
+        "JOB {} {} {} {} {} {} {}\n",
+        seed,
+        encode_budget(job.config.budget),
+        job.config.min_beam_width,
+        job.config.max_beam_width,
+        job.config.endgame_depth,
+        job.config.selection,
+        job.checkpoint.len()
+    );
+    for brick_stack in &job.checkpoint {
+        message.push_str("STATE ");
+        message.push_str(&encode_brick_stack(brick_stack));
+        message.push('\n');
+    }
+    writer.write_all(message.as_bytes())?;
+    writer.flush()
+}
+
+/// Read the next message from the coordinator, `None` when the connection was closed.
+pub fn read_coordinator_message(reader: &mut impl BufRead) -> io::Result<Option<CoordinatorMessage>> {
+    let mut line = String::new();
+    if read_line(reader, &mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut fields = line.split_whitespace();
+    match fields.next() {
+        Some("STOP") => Ok(Some(CoordinatorMessage::Stop)),
+        Some("JOB") => {
+            let seed = match fields.next() {
+                Some("-") => None,
+                seed => Some(parse_field(seed, "seed")?),
+            };
+            let budget = decode_budget(fields.next().unwrap_or_default())?;
+            let config = SearchConfig {
+                budget,
+                min_beam_width: parse_field(fields.next(), "min_beam")?,
+                max_beam_width: parse_field(fields.next(), "max_beam")?,
+                endgame_depth: parse_field(fields.next(), "endgame")?,
+                seed,
+                selection: parse_field(fields.next(), "selection")?,
+            };
+            let count: usize = parse_field(fields.next(), "state count")?;
+            let mut checkpoint = Vec::with_capacity(count.min(MAX_BRICKS_COUNT));
+            for _ in 0..count {
+                line.clear();
+                read_line(reader, &mut line)?;
+                match line.split_once(' ') {
+                    Some(("STATE", brick_stack)) => checkpoint.push(decode_brick_stack(brick_stack)?),
+                    _ => return Err(invalid_data(format!("expected STATE, got: {}", line.trim()))),
+                }
+            }
+            Ok(Some(CoordinatorMessage::Job(Job { config, checkpoint })))
+        }
+        _ => Err(invalid_data(format!("unknown message: {}", line.trim()))),
+    }
+}
+
+fn encode_grid(grids: &GameGrids) -> String {
+    grids
+        .bits()
+        .iter()
+        .map(|bits| format!("{:x}", bits))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn decode_grid(text: &str) -> io::Result<GameGrids> {
+    let mut bits = [0; 5];
+    let mut words = text.split(',');
+    for bits in &mut bits {
+        let word = words.next().unwrap_or_default();
+        *bits = u64::from_str_radix(word, 16)
+            .map_err(|_| invalid_data(format!("invalid grid word: {}", word)))?;
+    }
+    Ok(GameGrids::from_bits(bits))
+}
+
+pub fn write_best(writer: &mut impl Write, state: &GameState) -> io::Result<()> {
+    writeln!(
+        writer,
+        "BEST {} {} {} {} {}",
+        state.score,
+        state.sp_score,
+        state.brick_count,
+        state.rand_num,
+        encode_grid(&state.grids)
+    )?;
+    writer.flush()
+}
+
+pub fn write_beam(writer: &mut impl Write, stats: &BeamStats) -> io::Result<()> {
+    let mut line = format!(
+        "BEAM {} {} {} {} {}",
+        stats.width, stats.min, stats.median, stats.max, stats.profiles
+    );
+    for grids in &stats.top {
+        line.push(' ');
+        line.push_str(&encode_grid(grids));
+    }
+    writeln!(writer, "{}", line)?;
+    writer.flush()
+}
+
+pub fn write_elite(writer: &mut impl Write, state: &GameState) -> io::Result<()> {
+    writeln!(writer, "ELITE {}", encode_brick_stack(&state.brick_stack))?;
+    writer.flush()
+}
+
+pub fn write_dead_end(writer: &mut impl Write, dead_end: &DeadEnd) -> io::Result<()> {
+    let (from, width) = match dead_end.restart {
+        Some((from, width)) => (from.to_string(), width.to_string()),
+        None => ("-".to_string(), "-".to_string()),
+    };
+    writeln!(writer, "DEAD {} {} {} {}", dead_end.brick_count, dead_end.attempt, from, width)?;
+    writer.flush()
+}
+
+pub fn write_result(writer: &mut impl Write, state: &GameState) -> io::Result<()> {
+    writeln!(writer, "RESULT {}", encode_brick_stack(&state.brick_stack))?;
+    writer.flush()
+}
+
+pub fn write_error(writer: &mut impl Write, message: &str) -> io::Result<()> {
+    writeln!(writer, "ERROR {}", message.replace('\n', " "))?;
+    writer.flush()
+}
+
+/// Read the next message from a worker, `None` when the connection was closed.
+pub fn read_worker_message(reader: &mut impl BufRead) -> io::Result<Option<WorkerMessage>> {
+    let mut line = String::new();
+    if read_line(reader, &mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut fields = line.split_whitespace();
+    match fields.next() {
+        Some("BEST") => {
+            let mut state = GameState {
+                score: parse_field(fields.next(), "score")?,
+                sp_score: parse_field(fields.next(), "sp_score")?,
+                brick_count: parse_field(fields.next(), "brick_count")?,
+                rand_num: parse_field(fields.next(), "rand_num")?,
+                ..GameState::default()
+            };
+            state.grids = decode_grid(fields.next().unwrap_or_default())?;
+            Ok(Some(WorkerMessage::Best(state)))
+        }
+        Some("BEAM") => Ok(Some(WorkerMessage::Beam(BeamStats {
+            width: parse_field(fields.next(), "width")?,
+            min: parse_field(fields.next(), "min")?,
+            median: parse_field(fields.next(), "median")?,
+            max: parse_field(fields.next(), "max")?,
+            profiles: parse_field(fields.next(), "profiles")?,
+            top: fields.map(decode_grid).collect::<io::Result<_>>()?,
+        }))),
+        Some("ELITE") => Ok(Some(WorkerMessage::Elite(decode_brick_stack(
+            fields.next().unwrap_or_default(),
+        )?))),
+        Some("DEAD") => {
+            let brick_count = parse_field(fields.next(), "brick_count")?;
+            let attempt = parse_field(fields.next(), "attempt")?;
+            let restart = match fields.next() {
+                Some("-") => None,
+                from => Some((parse_field(from, "from")?, parse_field(fields.next(), "width")?)),
+            };
+            Ok(Some(WorkerMessage::DeadEnd(DeadEnd { brick_count, attempt, restart })))
+        }
+        Some("RESULT") => Ok(Some(WorkerMessage::Result(decode_brick_stack(
+            fields.next().unwrap_or_default(),
+        )?))),
+        Some("ERROR") => Ok(Some(WorkerMessage::Error(fields.collect::<Vec<_>>().join(" ")))),
+        _ => Err(invalid_data(format!("unknown message: {}", line.trim()))),
+    }
+}
+
+struct TcpReporter<'a> {
+    stream: &'a mut TcpStream,
+}
+
+impl StateReporter for TcpReporter<'_> {
+    fn report(&mut self, state: &GameState) -> io::Result<()> {
+        write_best(self.stream, state)
+    }
+
+    fn report_elites(&mut self, states: &[&GameState]) -> io::Result<()> {
+        for state in states {
+            write_elite(self.stream, state)?;
+        }
+        Ok(())
+    }
+
+    fn report_beam(&mut self, stats: &BeamStats) -> io::Result<()> {
+        write_beam(self.stream, stats)
+    }
+
+    fn report_dead_end(&mut self, dead_end: &DeadEnd) -> io::Result<()> {
+        write_dead_end(self.stream, dead_end)
+    }
+}
+
+/// Connect to a coordinator and run the jobs it hands out until it closes the
+/// connection or sends `STOP`.
+pub fn run_worker(addr: impl ToSocketAddrs) -> io::Result<()> {
+    let stream = TcpStream::connect(addr)?;
+    let mut writer = stream.try_clone()?;
+    let (job_snd, job_rcv) = channel::<(Job, BusReader<()>)>();
+
+    thread::spawn(move || {
+        let mut stop_bus = Bus::new(1);
+        let mut reader = BufReader::new(stream);
+        while let Ok(Some(CoordinatorMessage::Job(job))) = read_coordinator_message(&mut reader) {
+            if job_snd.send((job, stop_bus.add_rx())).is_err() {
+                return;
+            }
+        }
+        stop_bus.broadcast(());
+    });
+
+    while let Ok((job, mut stop_rx)) = job_rcv.recv() {
+        let initial_states = job
+            .checkpoint
+            .iter()
+            .map(|brick_stack| GameState::try_from_brick_stack(brick_stack))
+            .collect::<Result<Vec<_>, _>>();
+        let initial_states = match initial_states {
+            Ok(states) => states,
+            Err(err) => {
+                write_error(&mut writer, &err)?;
+                continue;
+            }
+        };
+        let mut reporter = TcpReporter { stream: &mut writer };
+        let final_state =
+            TetrisAuto::start_from(initial_states, None, &job.config, &mut stop_rx, &mut reporter);
+        write_result(&mut writer, &final_state)?;
+    }
+    Ok(())
+}
+
+enum Event {
+    Best(usize, GameState),
+    Beam(usize, BeamStats),
+    DeadEnd(usize, DeadEnd),
+    Result(GameState),
+    Disconnected(usize),
+}
+
+/// Hands out jobs to the workers that connect and keeps the global best.
+///
+/// Unless a fixed checkpoint is given, jobs branch from the elite archive
+/// filled by the workers, see `EliteArchive::pick`.
+pub struct Coordinator {
+    listener: TcpListener,
+    config: SearchConfig,
+    checkpoint: Vec<Vec<u16>>,
+    archive: EliteArchive,
+    archive_path: Option<String>,
+}
+
+impl Coordinator {
+    pub fn bind(addr: impl ToSocketAddrs, config: SearchConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        // `run` polls for new workers between events.
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            config,
+            checkpoint: Vec::new(),
+            archive: EliteArchive::new(BRANCH_DEPTHS, ELITES_PER_DEPTH),
+            archive_path: None,
+        })
+    }
+
+    /// Load the elite archive from `path` if it exists and keep it saved there.
+    pub fn with_archive(mut self, path: Option<String>) -> io::Result<Self> {
+        if let Some(path) = &path {
+            match self.archive.load(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => (),
+            }
+        }
+        self.archive_path = path;
+        Ok(self)
+    }
+
+    /// Start every job from these states instead of the empty board.
+    pub fn with_checkpoint(mut self, checkpoint: Vec<Vec<u16>>) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve workers until `kill_rx` fires and every worker has disconnected.
+    /// With a bounded budget each worker runs a single job and the coordinator
+    /// also returns once all workers are done.
+    ///
+    /// `on_update` is called with the progress of the workers at most every
+    /// `REFRESH_INTERVAL`, and every new best complete game is saved to
+    /// `op_sequence_<score>`. Returns the best result, complete games first, or
+    /// the best reported state if no worker finished a job.
+    pub fn run(self, mut kill_rx: BusReader<()>, mut on_update: impl FnMut(&Dashboard)) -> GameState {
+        let repeat = matches!(self.config.budget, SearchBudget::Unlimited);
+        let base_seed = self.config.seed.unwrap_or_else(rand::random);
+        let jobs = Arc::new(JobSource {
+            config: self.config,
+            checkpoint: self.checkpoint,
+            archive: Mutex::new(self.archive),
+            archive_path: self.archive_path,
+            archive_version: AtomicU64::new(0),
+            saved_version: Mutex::new(0),
+            rng: Mutex::new(StdRng::seed_from_u64(base_seed)),
+            next_seed: AtomicU64::new(base_seed),
+            stopping: AtomicBool::new(false),
+            repeat,
+        });
+
+        let (event_snd, event_rcv) = channel();
+        let mut workers = HashMap::<usize, Arc<Mutex<TcpStream>>>::new();
+        let mut next_id = 0;
+        let mut served = false;
+        let mut dashboard = Dashboard::new().with_selection(jobs.config.selection);
+        let mut updated_at = Instant::now();
+        let mut best_result: Option<GameState> = None;
+        loop {
+            while let Ok((stream, _)) = self.listener.accept() {
+                let writer = match stream.set_nonblocking(false).and_then(|_| stream.try_clone()) {
+                    Ok(writer) => Arc::new(Mutex::new(writer)),
+                    Err(_) => continue,
+                };
+                workers.insert(next_id, writer.clone());
+                let jobs = jobs.clone();
+                let event_snd = event_snd.clone();
+                thread::spawn(move || serve_worker(next_id, stream, writer, &jobs, &event_snd));
+                next_id += 1;
+                served = true;
+            }
+
+            if !jobs.stopping.load(Ordering::SeqCst) && kill_rx.try_recv().is_ok() {
+                jobs.stopping.store(true, Ordering::SeqCst);
+                for writer in workers.values() {
+                    writer.lock().unwrap().write_all(b"STOP\n").ok();
+                }
+            }
+
+            match event_rcv.recv_timeout(POLL_INTERVAL) {
+                Ok(Event::Best(id, state)) => dashboard.update(id, &state),
+                Ok(Event::Beam(id, stats)) => dashboard.update_beam(id, stats),
+                Ok(Event::DeadEnd(id, dead_end)) => dashboard.update_dead_end(id, dead_end),
+                Ok(Event::Result(state)) => {
+                    dashboard.offer_best(&state);
+                    let rank = |state: &GameState| (state.brick_count == MAX_BRICKS_COUNT, state.score);
+                    if best_result.as_ref().is_none_or(|result| rank(&state) > rank(result)) {
+                        if state.brick_count == MAX_BRICKS_COUNT {
+                            let sequence = state.get_op_sequence().to_op_string();
+                            std::fs::write(format!("op_sequence_{}", state.score), sequence).ok();
+                        }
+                        best_result = Some(state);
+                    }
+                }
+                Ok(Event::Disconnected(id)) => {
+                    workers.remove(&id);
+                    dashboard.remove(id);
+                }
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => (),
+            }
+            if updated_at.elapsed() >= REFRESH_INTERVAL {
+                on_update(&dashboard);
+                updated_at = Instant::now();
+            }
+
+            let stopping = jobs.stopping.load(Ordering::SeqCst);
+            if workers.is_empty() && (stopping || (served && !repeat)) {
+                break;
+            }
+        }
+
+        on_update(&dashboard);
+        best_result.unwrap_or_else(|| dashboard.best().clone())
+    }
+}
+
+struct JobSource {
+    config: SearchConfig,
+    checkpoint: Vec<Vec<u16>>,
+    archive: Mutex<EliteArchive>,
+    archive_path: Option<String>,
+    /// Bumped under the `archive` lock on every change to be saved.
+    archive_version: AtomicU64,
+    /// The version last written to `archive_path`, held while writing.
+    saved_version: Mutex<u64>,
+    rng: Mutex<StdRng>,
+    next_seed: AtomicU64,
+    stopping: AtomicBool,
+    repeat: bool,
+}
+
+impl JobSource {
+    fn next_job(&self) -> Job {
+        let mut config = self.config.clone();
+        config.seed = Some(self.next_seed.fetch_add(1, Ordering::SeqCst));
+        let checkpoint = match self.checkpoint.is_empty() {
+            false => self.checkpoint.clone(),
+            true => {
+                let archive = self.archive.lock().unwrap();
+                let mut rng = self.rng.lock().unwrap();
+                archive.pick(&mut *rng).map(|prefix| vec![prefix.to_vec()]).unwrap_or_default()
+            }
+        };
+        Job { config, checkpoint }
+    }
+
+    /// Replay and offer an elite reported by a worker. The archive file is
+    /// written after releasing the lock, skipping it if a newer version
+    /// has been written meanwhile.
+    fn add_elite(&self, brick_stack: &[u16]) -> io::Result<()> {
+        let state = GameState::try_from_brick_stack(brick_stack).map_err(invalid_data)?;
+        let (version, content) = {
+            let mut archive = self.archive.lock().unwrap();
+            if !archive.offer(&state) || self.archive_path.is_none() {
+                return Ok(());
+            }
+            (self.archive_version.fetch_add(1, Ordering::SeqCst) + 1, archive.to_text())
+        };
+        if let Some(path) = &self.archive_path {
+            let mut saved_version = self.saved_version.lock().unwrap();
+            if version > *saved_version {
+                fs::write(path, content).ok();
+                *saved_version = version;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn serve_worker(
+    id: usize,
+    stream: TcpStream,
+    writer: Arc<Mutex<TcpStream>>,
+    jobs: &JobSource,
+    events: &Sender<Event>,
+) {
+    let send_job = || write_job(&mut *writer.lock().unwrap(), &jobs.next_job());
+    if send_job().is_ok() {
+        let mut reader = BufReader::new(stream);
+        while let Ok(Some(message)) = read_worker_message(&mut reader) {
+            match message {
+                WorkerMessage::Best(state) => {
+                    events.send(Event::Best(id, state)).ok();
+                }
+                WorkerMessage::Beam(stats) => {
+                    events.send(Event::Beam(id, stats)).ok();
+                }
+                WorkerMessage::Elite(brick_stack) => {
+                    if jobs.add_elite(&brick_stack).is_err() {
+                        break;
+                    }
+                }
+                WorkerMessage::DeadEnd(dead_end) => {
+                    events.send(Event::DeadEnd(id, dead_end)).ok();
+                }
+                WorkerMessage::Result(brick_stack) => {
+                    // A result that can't be replayed is dropped with its worker.
+                    let state = match GameState::try_from_brick_stack(&brick_stack) {
+                        Ok(state) => state,
+                        Err(_) => break,
+                    };
+                    events.send(Event::Result(state)).ok();
+                    if jobs.stopping.load(Ordering::SeqCst) || !jobs.repeat || send_job().is_err() {
+                        break;
+                    }
+                }
+                WorkerMessage::Error(_) => break,
+            }
+        }
+    }
+    writer.lock().unwrap().shutdown(std::net::Shutdown::Both).ok();
+    events.send(Event::Disconnected(id)).ok();
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, AtomicU64},
+            mpsc::channel,
+            Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use bus::Bus;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        archive::EliteArchive,
+        auto::DeadEnd,
+        budget::{SearchBudget, SearchConfig},
+        dashboard::BeamStats,
+        game::{greedy_playout, GameState, MAX_BRICKS_COUNT, MAX_PLACEMENTS},
+        selection::Selection,
+    };
+
+    use super::{
+        decode_brick_stack, encode_brick_stack, load_checkpoint, read_coordinator_message, read_worker_message,
+        run_worker, write_beam, write_best, write_dead_end, write_error, write_job, Coordinator, CoordinatorMessage,
+        Job, JobSource, WorkerMessage, MAX_LINE_LEN,
+    };
+
+    fn small_config() -> SearchConfig {
+        SearchConfig {
+            budget: SearchBudget::Cpu(Duration::from_millis(200)),
+            min_beam_width: 1,
+            max_beam_width: 8,
+            endgame_depth: 2,
+            seed: Some(7),
+            selection: Selection::ParentCap(3),
+        }
+    }
+
+    #[test]
+    fn test_brick_stack_encoding() {
+        let brick_stack = vec![0x0000, 0x1234, 0xffff, 0x0c4a];
+        assert_eq!(encode_brick_stack(&brick_stack), "00001234ffff0c4a");
+        assert_eq!(decode_brick_stack("00001234ffff0c4a\n").unwrap(), brick_stack);
+        assert!(decode_brick_stack("123").is_err());
+    }
+
+    #[test]
+    fn test_job_round_trip() {
+        let job = Job {
+            config: small_config(),
+            checkpoint: vec![vec![0x0134, 0x0012], vec![]],
+        };
+        let mut buffer = Vec::new();
+        write_job(&mut buffer, &job).unwrap();
+        let message = read_coordinator_message(&mut BufReader::new(&buffer[..])).unwrap();
+        match message {
+            Some(CoordinatorMessage::Job(parsed)) => {
+                assert_eq!(parsed.config.seed, Some(7));
+                assert_eq!(parsed.config.max_beam_width, 8);
+                assert_eq!(parsed.config.endgame_depth, 2);
+                assert_eq!(parsed.config.selection, Selection::ParentCap(3));
+                assert!(matches!(parsed.config.budget, SearchBudget::Cpu(limit) if limit == Duration::from_millis(200)));
+                assert_eq!(parsed.checkpoint, job.checkpoint);
+            }
+            _ => panic!("expected a job"),
+        }
+    }
+
+    #[test]
+    fn test_best_round_trip() {
+        let state = greedy_playout(&GameState::initial_state(), 1);
+        let mut buffer = Vec::new();
+        write_best(&mut buffer, &state).unwrap();
+        match read_worker_message(&mut BufReader::new(&buffer[..])).unwrap() {
+            Some(WorkerMessage::Best(parsed)) => {
+                assert_eq!(parsed.grids.bits(), state.grids.bits());
+                assert_eq!(parsed.brick_count, 1);
+                assert_eq!(parsed.rand_num, state.rand_num);
+            }
+            _ => panic!("expected a summary"),
+        }
+    }
+
+    #[test]
+    fn test_beam_round_trip() {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let len = GameState::initial_state().next(&mut next_states);
+        let stats = BeamStats::from_beam(&next_states[..len], 3);
+        let mut buffer = Vec::new();
+        write_beam(&mut buffer, &stats).unwrap();
+        match read_worker_message(&mut BufReader::new(&buffer[..])).unwrap() {
+            Some(WorkerMessage::Beam(parsed)) => assert_eq!(parsed, stats),
+            _ => panic!("expected a beam summary"),
+        }
+    }
+
+    #[test]
+    fn test_dead_end_round_trip() {
+        let retried = DeadEnd { brick_count: 452, attempt: 3, restart: Some((400, 2048)) };
+        for dead_end in [retried, DeadEnd { restart: None, ..retried }] {
+            let mut buffer = Vec::new();
+            write_dead_end(&mut buffer, &dead_end).unwrap();
+            match read_worker_message(&mut BufReader::new(&buffer[..])).unwrap() {
+                Some(WorkerMessage::DeadEnd(parsed)) => assert_eq!(parsed, dead_end),
+                _ => panic!("expected a dead end"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_round_trip() {
+        let mut buffer = Vec::new();
+        write_error(&mut buffer, "invalid placement\nof brick 3").unwrap();
+        match read_worker_message(&mut BufReader::new(&buffer[..])).unwrap() {
+            Some(WorkerMessage::Error(message)) => assert_eq!(message, "invalid placement of brick 3"),
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_line_limit() {
+        let mut long_line = "BEAM ".to_string() + &"0".repeat(MAX_LINE_LEN);
+        long_line.push('\n');
+        assert!(read_worker_message(&mut BufReader::new(long_line.as_bytes())).is_err());
+        assert!(read_coordinator_message(&mut BufReader::new(long_line.as_bytes())).is_err());
+
+        // A complete game still fits.
+        let result = format!("RESULT {}\n", encode_brick_stack(&[0; MAX_BRICKS_COUNT]));
+        match read_worker_message(&mut BufReader::new(result.as_bytes())).unwrap() {
+            Some(WorkerMessage::Result(brick_stack)) => assert_eq!(brick_stack.len(), MAX_BRICKS_COUNT),
+            _ => panic!("expected a result"),
+        }
+    }
+
+    #[test]
+    fn test_load_checkpoint() {
+        let path = std::env::temp_dir().join(format!("tetris_checkpoint_{}", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        let brick_stack = greedy_playout(&GameState::initial_state(), 1).brick_stack;
+
+        fs::write(&path, format!("{}\n\n", encode_brick_stack(&brick_stack))).unwrap();
+        assert_eq!(load_checkpoint(path_str).unwrap(), vec![brick_stack.clone()]);
+        // y = 31 is below the board.
+        fs::write(&path, format!("{}\nffff\n", encode_brick_stack(&brick_stack))).unwrap();
+        let err = load_checkpoint(path_str).unwrap_err();
+        assert!(err.to_string().starts_with("line 2: "));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_worker_rejects_invalid_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let worker = thread::spawn(move || run_worker(addr));
+        let (mut stream, _) = listener.accept().unwrap();
+        // y = 31 is below the board and would have panicked the worker.
+        let job = Job { config: small_config(), checkpoint: vec![vec![0xffff]] };
+        write_job(&mut stream, &job).unwrap();
+        let mut line = String::new();
+        BufReader::new(stream.try_clone().unwrap()).read_line(&mut line).unwrap();
+        assert!(line.starts_with("ERROR "), "{}", line);
+        drop(stream);
+        worker.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_jobs_branch_from_archive() {
+        let coordinator = Coordinator::bind("127.0.0.1:0", small_config()).unwrap();
+        let jobs = JobSource {
+            config: coordinator.config,
+            checkpoint: Vec::new(),
+            archive: Mutex::new(EliteArchive::new(&[1], 4)),
+            archive_path: None,
+            archive_version: AtomicU64::new(0),
+            saved_version: Mutex::new(0),
+            rng: Mutex::new(StdRng::seed_from_u64(3)),
+            next_seed: AtomicU64::new(10),
+            stopping: AtomicBool::new(false),
+            repeat: true,
+        };
+        assert!(jobs.next_job().checkpoint.is_empty());
+
+        let elite = greedy_playout(&GameState::initial_state(), 1).brick_stack;
+        jobs.add_elite(&elite).unwrap();
+        assert!(jobs.add_elite(&[0xffff]).is_err());
+        let jobs = (0..20).map(|_| jobs.next_job()).collect::<Vec<_>>();
+        assert!(jobs.iter().any(|job| job.checkpoint == vec![elite.clone()]));
+        assert!(jobs.iter().any(|job| job.checkpoint.is_empty()));
+        assert_eq!(jobs[0].config.seed.unwrap() + 1, jobs[1].config.seed.unwrap());
+    }
+
+    #[test]
+    fn test_coordinator_survives_disconnect() {
+        let config = SearchConfig {
+            budget: SearchBudget::Unlimited,
+            ..small_config()
+        };
+        let coordinator = Coordinator::bind("127.0.0.1:0", config).unwrap();
+        let addr = coordinator.local_addr().unwrap();
+        let mut kill_bus = Bus::new(1);
+        let kill_rx = kill_bus.add_rx();
+        // Tell the test once a worker has reported progress.
+        let (progress_snd, progress_rcv) = channel();
+        let handle = thread::spawn(move || {
+            coordinator.run(kill_rx, |dashboard| {
+                if dashboard.best().brick_count > 0 {
+                    progress_snd.send(()).ok();
+                }
+            })
+        });
+
+        // A worker that hangs up right away, and one that sends garbage.
+        drop(TcpStream::connect(addr).unwrap());
+        TcpStream::connect(addr).unwrap().write_all(b"HELLO\n").unwrap();
+
+        let worker = thread::spawn(move || run_worker(addr));
+        progress_rcv.recv().unwrap();
+        kill_bus.broadcast(());
+        worker.join().unwrap().unwrap();
+        let best = handle.join().unwrap();
+        assert!(best.brick_count > 0);
+        assert_eq!(GameState::from_brick_stack(&best.brick_stack).score, best.score);
+    }
+
+    #[test]
+    fn test_invalid_result_disconnects() {
+        let coordinator = Coordinator::bind("127.0.0.1:0", small_config()).unwrap();
+        let addr = coordinator.local_addr().unwrap();
+        let mut kill_bus = Bus::<()>::new(1);
+        let kill_rx = kill_bus.add_rx();
+        let handle = thread::spawn(move || coordinator.run(kill_rx, |_| ()));
+
+        // y = 31 is below the board and would have panicked the replay.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"RESULT 0000ffff\n").unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert!(received.starts_with("JOB "));
+        let best = handle.join().unwrap();
+        assert_eq!(best.brick_count, 0);
+    }
+}
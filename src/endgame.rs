@@ -0,0 +1,173 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    game::{encode_placement, GameState, ENDGAME_WINDOW, MAX_BRICKS_COUNT, MAX_PLACEMENTS},
+    vec2::Vec2,
+};
+
+type Placements = [(Vec2, usize); MAX_PLACEMENTS];
+
+/// Most last bricks searched exhaustively instead of by the beam. The search
+/// starts as soon as `estimate` says it fits the time left, so the whole
+/// window the heuristic fades out over can be searched when time allows.
+pub const DEFAULT_ENDGAME_DEPTH: usize = ENDGAME_WINDOW;
+/// Time the exhaustive search may take when the search budget is unlimited.
+pub const UNLIMITED_ENDGAME_TIME: Duration = Duration::from_secs(10);
+/// How many of the best beam states the exhaustive search starts from.
+pub const ENDGAME_ROOTS: usize = 16;
+
+/// Find the placements of the next `depth` bricks (fewer if the game ends
+/// before) that maximize the realized score, ignoring the heuristic terms.
+///
+/// A line that dies early ranks below any line that places more bricks.
+pub fn solve(state: &GameState, depth: usize) -> GameState {
+    let depth = depth.min(MAX_BRICKS_COUNT.saturating_sub(state.brick_count));
+    // Search from a copy without history so the brick stack of a leaf is the path.
+    let mut root = state.clone();
+    root.brick_stack = Vec::with_capacity(depth);
+    let mut buffers = vec![[(Vec2(0, 0), 0); MAX_PLACEMENTS]; depth];
+    let mut best = Leaf::default();
+    search(&mut root, &mut buffers, &mut best);
+
+    let mut result = state.clone();
+    for placement in best.path {
+        result.apply_placement(placement);
+    }
+    result
+}
+
+/// Run `solve` from the best `ENDGAME_ROOTS` states of `beam` by `sp_score`
+/// and return the best game found.
+pub fn solve_beam<'a>(beam: impl IntoIterator<Item = &'a GameState>, depth: usize) -> Option<GameState> {
+    roots(beam)
+        .into_iter()
+        .map(|root| solve(root, depth))
+        .max_by_key(|state| (state.brick_count, state.score))
+}
+
+/// Estimate how long `solve_beam(beam, depth)` takes by timing the first
+/// brick from each root: every brick below is assumed to have as many
+/// placements as the roots have on average.
+pub fn estimate<'a>(beam: impl IntoIterator<Item = &'a GameState>, depth: usize) -> Duration {
+    let mut roots = roots(beam).into_iter().cloned().collect::<Vec<_>>();
+    let mut placements = [(Vec2(0, 0), 0); MAX_PLACEMENTS];
+    let mut nodes = 0;
+    let started = Instant::now();
+    for root in &mut roots {
+        let len = root.placements(&mut placements);
+        for (pos, rot) in &placements[..len] {
+            let undo = root.make(encode_placement(*pos, *rot));
+            root.unmake(&undo);
+        }
+        nodes += len;
+    }
+    let elapsed = started.elapsed();
+    if nodes == 0 {
+        return elapsed;
+    }
+    let branching = nodes as f64 / roots.len() as f64;
+    let total_nodes = (1..=depth).map(|layer| branching.powi(layer as i32)).sum::<f64>() * roots.len() as f64;
+    Duration::try_from_secs_f64(elapsed.as_secs_f64() / nodes as f64 * total_nodes).unwrap_or(Duration::MAX)
+}
+
+/// The best `ENDGAME_ROOTS` states of `beam` by `sp_score`.
+fn roots<'a>(beam: impl IntoIterator<Item = &'a GameState>) -> Vec<&'a GameState> {
+    let mut roots = beam.into_iter().collect::<Vec<_>>();
+    roots.sort_by_key(|state| std::cmp::Reverse(state.sp_score));
+    roots.truncate(ENDGAME_ROOTS);
+    roots
+}
+
+/// The best line found so far.
+#[derive(Default)]
+struct Leaf {
+    key: Option<(usize, u32)>,
+    path: Vec<u16>,
+}
+
+/// Walk the lines below `state` depth first with `make`/`unmake`, keeping the
+/// first line of the highest (brick count, score) in `best`.
+fn search(state: &mut GameState, buffers: &mut [Placements], best: &mut Leaf) {
+    let len = match buffers.split_first_mut() {
+        Some((placements, rest)) => {
+            let len = state.placements(placements);
+            for (pos, rot) in &placements[..len] {
+                let undo = state.make(encode_placement(*pos, *rot));
+                search(state, rest, best);
+                state.unmake(&undo);
+            }
+            len
+        }
+        None => 0,
+    };
+    let key = (state.brick_count, state.score);
+    if len == 0 && best.key.is_none_or(|best| key > best) {
+        best.key = Some(key);
+        best.path.clone_from(&state.brick_stack);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::{greedy_playout, GameState, MAX_BRICKS_COUNT, MAX_PLACEMENTS};
+
+    use super::{estimate, solve, solve_beam};
+
+    #[test]
+    fn test_solve_beats_greedy() {
+        let start = greedy_playout(&GameState::initial_state(), 20);
+        let solved = solve(&start, 3);
+        assert_eq!(solved.brick_count, start.brick_count + 3);
+        assert!(solved.score >= greedy_playout(&start, 3).score);
+
+        let replayed = GameState::from_brick_stack(&solved.brick_stack);
+        assert_eq!(replayed.score, solved.score);
+        assert_eq!(replayed.grids, solved.grids);
+
+        let from_beam = solve_beam(&[start.clone(), greedy_playout(&start, 1)], 2).unwrap();
+        assert_eq!(from_beam.brick_count, start.brick_count + 3);
+    }
+
+    #[test]
+    fn test_last_brick_scores_nothing() {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let scores = |state: &GameState, next_states: &mut [GameState]| {
+            let len = state.next(next_states);
+            next_states[..len].iter().any(|next| next.score > state.score)
+        };
+        let mut state = GameState::initial_state();
+        while !scores(&state, &mut next_states) {
+            state = greedy_playout(&state, 1);
+        }
+
+        state.brick_count = MAX_BRICKS_COUNT - 1;
+        let len = state.next(&mut next_states);
+        assert!(len > 0);
+        assert!(next_states[..len].iter().all(|next| next.score == state.score));
+        assert_eq!(solve(&state, 3).brick_count, MAX_BRICKS_COUNT);
+    }
+
+    #[test]
+    fn test_depth_3_falls_short() {
+        // Five bricks before the end, the last one scores nothing. The best
+        // line of three bricks leaves the fourth less to clear than the best
+        // line of four.
+        let text = format!(
+            "score 0\nbrick_count {}\nrand_num 7037\n{}.#####.###\n######..##\n######..##\n",
+            MAX_BRICKS_COUNT - 5,
+            "..........\n".repeat(17)
+        );
+        let state = GameState::from_ascii(&text).unwrap();
+        let deep = solve(&state, 4);
+        let shallow = solve(&solve(&state, 3), 1);
+        assert_eq!((deep.score, shallow.score), (40, 36));
+    }
+
+    #[test]
+    fn test_estimate_grows_with_depth() {
+        let beam = [greedy_playout(&GameState::initial_state(), 20)];
+        assert!(estimate(&beam, 1) < estimate(&beam, 3));
+        assert!(estimate(&beam, 3) < estimate(&beam, 10));
+        assert!(estimate(&[], 3) < estimate(&beam, 3));
+    }
+}
@@ -0,0 +1,249 @@
+//! Breakdown of `sp_score` into its weighted heuristic terms, to see why the
+//! search prefers one board over another.
+
+use crate::{
+    brick::Brick,
+    game::{decode_placement, endgame_weight, Candidate, Features, GameState, ENDGAME_WINDOW, MAX_PLACEMENTS},
+    grid::{GameGrids, GRID_HEIGHT},
+    random::get_random_num,
+};
+
+/// Width of a column of `compare_lines`.
+const COLUMN_WIDTH: usize = 12;
+
+/// The terms of the `sp_score` of a board, see `Features`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    /// The explained `brick_stack` entry, `None` for a board as it stands.
+    pub placement: Option<u16>,
+    /// Bricks placed, including the explained one.
+    pub brick_count: usize,
+    pub features: Features,
+    /// The score including the points of `features.cleared_rows`.
+    pub score: u32,
+    /// Empty cells below an occupied cell of the same column, not weighted.
+    pub holes: usize,
+    /// Depth of the deepest column below both its neighbours, not weighted.
+    pub deepest_well: usize,
+    pub sp_score: i32,
+}
+
+impl Explanation {
+    /// Explain the board of `state` as if its last brick had just been placed.
+    /// That is `state.sp_score` unless the brick cleared rows, `evaluate`
+    /// measures the board before they are cleared.
+    pub fn of_state(state: &GameState) -> Self {
+        Self::measure(None, state.grids.clone(), state.score, state.brick_count)
+    }
+
+    /// Explain placing the next brick of `state` by `placement`, the
+    /// `sp_score` is the one of the resulting child state.
+    pub fn of_placement(state: &GameState, placement: u16) -> Self {
+        let (pos, rot) = decode_placement(placement);
+        let brick = Brick::from_random_num(get_random_num(state.rand_num), state.brick_count).rotate_n(rot);
+        let mut grids = state.grids.clone();
+        grids.place_teris_brick(&brick, pos);
+        Self::measure(Some(placement), grids, state.score, state.brick_count + 1)
+    }
+
+    fn measure(placement: Option<u16>, mut grids: GameGrids, score: u32, brick_count: usize) -> Self {
+        let features = Features::measure(&grids, brick_count);
+        if features.cleared_rows > 0 {
+            grids.remove_full_rows();
+        }
+        let score = score + features.clear_score();
+        Self {
+            placement,
+            brick_count,
+            features,
+            score,
+            holes: holes(&grids),
+            deepest_well: deepest_well(&grids),
+            sp_score: features.sp_score(score, brick_count),
+        }
+    }
+
+    /// Render the explanation as a table of the measured features and their
+    /// weighted terms.
+    pub fn lines(&self) -> Vec<String> {
+        let features = &self.features;
+        let measured = [
+            features.height.to_string(),
+            features.height.to_string(),
+            features.occupied_blocks.to_string(),
+            format!("{:.3}", features.density),
+        ];
+        let mut lines = vec![
+            format!("{}, {} bricks", placement_name(self.placement), self.brick_count),
+            format!("{:<18}{:>10}{:>10}", "term", "feature", "weighted"),
+        ];
+        for ((name, weighted), feature) in features.terms().iter().zip(&measured) {
+            lines.push(format!("{:<18}{:>10}{:>10}", name, feature, weighted));
+        }
+        lines.push(format!(
+            "{:<18}{:>10}{:>10}",
+            "heuristic",
+            format!("x {}/{}", endgame_weight(self.brick_count), ENDGAME_WINDOW),
+            features.heuristic() * endgame_weight(self.brick_count) / ENDGAME_WINDOW as i32
+        ));
+        lines.push(format!(
+            "{:<18}{:>10}{:>10}",
+            "score",
+            format!("+{}", features.clear_score()),
+            self.score
+        ));
+        lines.push(format!("{:<18}{:>10}{:>10}", "sp_score", "", self.sp_score));
+        lines.push(format!("{:<18}{:>10}{:>10}", "cleared rows", features.cleared_rows, "-"));
+        lines.push(format!("{:<18}{:>10}{:>10}", "holes", self.holes, "-"));
+        lines.push(format!("{:<18}{:>10}{:>10}", "deepest well", self.deepest_well, "-"));
+        lines
+    }
+
+    /// Weighted values by row name, the columns of `compare_lines`.
+    fn values(&self) -> Vec<(&'static str, String)> {
+        let placement = match self.placement {
+            Some(placement) => {
+                let (pos, rot) = decode_placement(placement);
+                format!("{},{} r{}", pos.0, pos.1, rot)
+            }
+            None => "board".to_string(),
+        };
+        let mut values = vec![("x,y rot", placement)];
+        values.extend(self.features.terms().iter().map(|(name, weighted)| (*name, weighted.to_string())));
+        values.push(("heuristic", self.features.heuristic().to_string()));
+        values.push(("cleared rows", self.features.cleared_rows.to_string()));
+        values.push(("score", self.score.to_string()));
+        values.push(("sp_score", self.sp_score.to_string()));
+        values.push(("holes", self.holes.to_string()));
+        values.push(("deepest well", self.deepest_well.to_string()));
+        values
+    }
+}
+
+/// Explain the `count` best placements of the next brick of `state` by
+/// `sp_score`, best first.
+pub fn top_placements(state: &GameState, count: usize) -> Vec<Explanation> {
+    let mut candidates = vec![Candidate::default(); MAX_PLACEMENTS];
+    let len = state.candidates(&mut candidates);
+    let mut explanations = candidates[..len]
+        .iter()
+        .map(|candidate| Explanation::of_placement(state, candidate.placement))
+        .collect::<Vec<_>>();
+    explanations.sort_by_key(|explanation| std::cmp::Reverse(explanation.sp_score));
+    explanations.truncate(count);
+    explanations
+}
+
+/// Render `explanations` side by side, one column each.
+pub fn compare_lines(explanations: &[Explanation]) -> Vec<String> {
+    let columns = explanations.iter().map(Explanation::values).collect::<Vec<_>>();
+    let names = columns.first().map_or_else(Vec::new, |values| {
+        values.iter().map(|(name, _)| *name).collect()
+    });
+    names
+        .iter()
+        .enumerate()
+        .map(|(row, name)| {
+            let mut line = format!("{:<18}", name);
+            for values in &columns {
+                line.push_str(&format!("{:>width$}", values[row].1, width = COLUMN_WIDTH));
+            }
+            line
+        })
+        .collect()
+}
+
+fn placement_name(placement: Option<u16>) -> String {
+    match placement {
+        Some(placement) => {
+            let (pos, rot) = decode_placement(placement);
+            format!("x {} y {} rot {}", pos.0, pos.1, rot)
+        }
+        None => "board".to_string(),
+    }
+}
+
+fn holes(grids: &GameGrids) -> usize {
+    let mut covered = 0;
+    let mut holes = 0;
+    for y in 0..GRID_HEIGHT as i8 {
+        let row = grids.get_row(y);
+        holes += (covered & !row).count_ones() as usize;
+        covered |= row;
+    }
+    holes
+}
+
+fn deepest_well(grids: &GameGrids) -> usize {
+    let heights = grids.column_heights();
+    (0..heights.len())
+        .map(|x| {
+            let left = if x == 0 { GRID_HEIGHT as u8 } else { heights[x - 1] };
+            let right = heights.get(x + 1).copied().unwrap_or(GRID_HEIGHT as u8);
+            left.min(right).saturating_sub(heights[x]) as usize
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        game::{Candidate, GameState, MAX_PLACEMENTS},
+        grid::GameGrids,
+        vec2::Vec2,
+    };
+
+    use super::{compare_lines, deepest_well, holes, top_placements, Explanation};
+
+    #[test]
+    fn test_placements_match_children() {
+        let mut candidates = vec![Candidate::default(); MAX_PLACEMENTS];
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let mut state = GameState::initial_state();
+        for _ in 0..40 {
+            let len = state.candidates(&mut candidates);
+            assert_eq!(state.next(&mut next_states), len);
+            for (candidate, child) in candidates[..len].iter().zip(&next_states) {
+                let explanation = Explanation::of_placement(&state, candidate.placement);
+                assert_eq!((explanation.score, explanation.sp_score), (child.score, child.sp_score));
+                let heuristic = explanation.features.terms().iter().map(|(_, value)| value).sum::<i32>();
+                assert_eq!(heuristic, explanation.features.heuristic());
+                if explanation.features.cleared_rows == 0 {
+                    assert_eq!(Explanation::of_state(child).sp_score, child.sp_score);
+                }
+            }
+            match next_states[..len].iter().max_by_key(|state| state.sp_score) {
+                Some(best) => state = best.clone(),
+                None => break,
+            }
+        }
+    }
+
+    #[test]
+    fn test_holes_and_wells() {
+        let mut grids = GameGrids::new();
+        assert_eq!((holes(&grids), deepest_well(&grids)), (0, 0));
+        for x in (0..10).filter(|x| *x != 3) {
+            grids.set_block(Vec2(x, 19));
+        }
+        for pos in [Vec2(2, 18), Vec2(4, 18), Vec2(7, 17)] {
+            grids.set_block(pos);
+        }
+        assert_eq!((holes(&grids), deepest_well(&grids)), (1, 2));
+    }
+
+    #[test]
+    fn test_top_placements() {
+        let state = GameState::initial_state();
+        let top = top_placements(&state, 3);
+        assert_eq!(top.len(), 3);
+        assert!(top.windows(2).all(|pair| pair[0].sp_score >= pair[1].sp_score));
+        let lines = compare_lines(&top);
+        assert_eq!(lines.len(), 11);
+        assert!(lines[0].starts_with("x,y rot"));
+        assert!(lines.iter().any(|line| line.starts_with("sp_score")
+            && line.contains(&top[0].sp_score.to_string())));
+        assert!(Explanation::of_state(&state).lines().iter().any(|line| line.starts_with("density")));
+    }
+}
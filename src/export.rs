@@ -0,0 +1,494 @@
+//! Export of a replay as an animated GIF, an SVG sprite sheet or a directory
+//! of PNG frames.
+//!
+//! All formats draw the frames of `Replay` with the same layout: an optional
+//! overlay bar with the score on the left and the brick count on the right,
+//! above the board.
+
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    grid::{GRID_HEIGHT, GRID_WIDTH},
+    op::GameOP,
+    render::parse_colors,
+    replay::{Frame, FrameStep, Replay},
+    vec2::Vec2,
+};
+
+const BACKGROUND: u8 = 0;
+const EMPTY: u8 = 1;
+const TEXT: u8 = 2;
+const SETTLED: u8 = 3;
+/// The falling brick uses the config color at `BRICK + brick_count % colors`.
+const BRICK: u8 = 4;
+
+/// Digits of a 3x5 pixel font, one byte per row with the leftmost pixel in bit 2.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+lazy_static::lazy_static! {
+    /// Palette shared by all frames, the brick colors come from game.config.js.
+    static ref PALETTE: Vec<[u8; 3]> = {
+        let colors = parse_colors(include_str!("game.config.js"));
+        let settled = colors.first().map_or([128, 128, 128], |rgb| rgb.map(|value| (value as u32 * 7 / 10) as u8));
+        let mut palette = vec![[16, 16, 16], [40, 40, 40], [255, 255, 255], settled];
+        palette.extend(colors);
+        palette
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Gif,
+    /// All frames in a grid in one SVG.
+    Svg,
+    /// `frame_00000.png`, ... in a directory.
+    PngDir,
+}
+
+impl ExportFormat {
+    /// Guess the format from the extension of the output path.
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("gif") => Self::Gif,
+            Some("svg") => Self::Svg,
+            _ => Self::PngDir,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "gif" => Ok(Self::Gif),
+            "svg" => Ok(Self::Svg),
+            "png" => Ok(Self::PngDir),
+            _ => Err(format!("unknown export format: {}", text)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub step: FrameStep,
+    /// Keep every `skip`-th frame. The last frame is always kept.
+    pub skip: usize,
+    pub score_overlay: bool,
+    /// Size of a board cell in pixels.
+    pub cell_size: usize,
+    /// Delay between GIF frames, in steps of 10ms.
+    pub frame_delay: Duration,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Gif,
+            step: FrameStep::PerBrick,
+            skip: 1,
+            score_overlay: true,
+            cell_size: 12,
+            frame_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Replay `ops` and write the frames to `output`. Returns the number of frames written.
+pub fn export(ops: Vec<GameOP>, output: &str, options: &ExportOptions) -> io::Result<usize> {
+    brick_colors(&PALETTE)?;
+    let layout = Layout::new(options);
+    layout.size()?;
+    let frames = sample(Replay::new(ops, options.step)?, options.skip);
+    match options.format {
+        ExportFormat::Gif => write_gif(frames, File::create(output)?, &layout, options.frame_delay),
+        ExportFormat::Svg => write_svg(frames, BufWriter::new(File::create(output)?), &layout),
+        ExportFormat::PngDir => write_pngs(frames, Path::new(output), &layout),
+    }
+}
+
+/// Number of brick colors in `palette`, there has to be at least one.
+fn brick_colors(palette: &[[u8; 3]]) -> io::Result<usize> {
+    match palette.len().saturating_sub(BRICK as usize) {
+        0 => Err(io::Error::new(io::ErrorKind::InvalidData, "game.config.js has no brick colors")),
+        colors => Ok(colors),
+    }
+}
+
+/// Every `skip`-th frame and the last one.
+fn sample(frames: impl Iterator<Item = Frame>, skip: usize) -> impl Iterator<Item = Frame> {
+    let skip = skip.max(1);
+    let mut frames = frames.enumerate().peekable();
+    std::iter::from_fn(move || loop {
+        let (idx, frame) = frames.next()?;
+        if idx % skip == 0 || frames.peek().is_none() {
+            return Some(frame);
+        }
+    })
+}
+
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    color: u8,
+}
+
+/// Pixel geometry of a frame.
+struct Layout {
+    cell: usize,
+    /// Pixel size of the overlay font.
+    font_scale: usize,
+    overlay_height: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Layout {
+    fn new(options: &ExportOptions) -> Self {
+        let cell = options.cell_size.max(2);
+        let font_scale = (cell / 6).max(1);
+        let overlay_height = if options.score_overlay { 7 * font_scale } else { 0 };
+        Self {
+            cell,
+            font_scale,
+            overlay_height,
+            width: GRID_WIDTH as usize * cell,
+            height: overlay_height + GRID_HEIGHT as usize * cell,
+        }
+    }
+
+    /// Width and height of a frame, GIF and PNG frames are at most `u16::MAX` pixels on a side.
+    fn size(&self) -> io::Result<(u16, u16)> {
+        match (u16::try_from(self.width), u16::try_from(self.height)) {
+            (Ok(width), Ok(height)) => Ok((width, height)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "frames of {}x{} pixels, at most {} on a side",
+                    self.width,
+                    self.height,
+                    u16::MAX
+                ),
+            )),
+        }
+    }
+
+    fn cell_rect(&self, pos: Vec2, color: u8) -> Rect {
+        // Leave a one pixel gap so the blocks stay apart.
+        Rect {
+            x: pos.0 as usize * self.cell,
+            y: self.overlay_height + pos.1 as usize * self.cell,
+            width: self.cell - 1,
+            height: self.cell - 1,
+            color,
+        }
+    }
+
+    /// The board cells of `frame`, drawn in order.
+    fn rects(&self, frame: &Frame) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for y in 0..GRID_HEIGHT as i8 {
+            for x in 0..GRID_WIDTH as i8 {
+                let color = if frame.grids.get(Vec2(x, y)) { SETTLED } else { EMPTY };
+                rects.push(self.cell_rect(Vec2(x, y), color));
+            }
+        }
+        if let Some((brick, pos)) = frame.brick {
+            let color = BRICK + (frame.brick_count % (PALETTE.len() - BRICK as usize)) as u8;
+            for cell in brick.pos_with_center(pos) {
+                // Cells above the board while spawning are not drawn.
+                if cell.1 >= 0 {
+                    rects.push(self.cell_rect(cell, color));
+                }
+            }
+        }
+        rects
+    }
+
+    fn text_width(&self, text: &str) -> usize {
+        (text.len() * 4).saturating_sub(1) * self.font_scale
+    }
+
+    /// The overlay texts and their left x.
+    fn overlay(&self, frame: &Frame) -> Option<[(usize, String); 2]> {
+        if self.overlay_height == 0 {
+            return None;
+        }
+        let score = frame.score.to_string();
+        let bricks = frame.brick_count.to_string();
+        let right = self.width.saturating_sub(self.font_scale + self.text_width(&bricks));
+        Some([(self.font_scale, score), (right, bricks)])
+    }
+}
+
+/// A frame as palette indices.
+struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Raster {
+    fn draw(frame: &Frame, layout: &Layout) -> Self {
+        let mut raster = Self {
+            width: layout.width,
+            height: layout.height,
+            pixels: vec![BACKGROUND; layout.width * layout.height],
+        };
+        for rect in layout.rects(frame) {
+            raster.fill(&rect);
+        }
+        for (x, text) in layout.overlay(frame).into_iter().flatten() {
+            raster.text(x, layout.font_scale, layout.font_scale, &text);
+        }
+        raster
+    }
+
+    fn fill(&mut self, rect: &Rect) {
+        for y in rect.y..(rect.y + rect.height).min(self.height) {
+            let row = y * self.width;
+            for x in rect.x..(rect.x + rect.width).min(self.width) {
+                self.pixels[row + x] = rect.color;
+            }
+        }
+    }
+
+    /// Draw the digits of `text` with the 3x5 font at `scale` pixels per font pixel.
+    fn text(&mut self, x: usize, y: usize, scale: usize, text: &str) {
+        for (idx, digit) in text.chars().filter_map(|ch| ch.to_digit(10)).enumerate() {
+            let left = x + idx * 4 * scale;
+            for (row, bits) in DIGITS[digit as usize].iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) != 0 {
+                        self.fill(&Rect {
+                            x: left + col * scale,
+                            y: y + row * scale,
+                            width: scale,
+                            height: scale,
+                            color: TEXT,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn export_error(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::other(err)
+}
+
+fn write_gif(
+    frames: impl Iterator<Item = Frame>,
+    output: impl Write,
+    layout: &Layout,
+    delay: Duration,
+) -> io::Result<usize> {
+    let palette = PALETTE.concat();
+    let (width, height) = layout.size()?;
+    let mut encoder = gif::Encoder::new(output, width, height, &palette).map_err(export_error)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(export_error)?;
+    let mut count = 0;
+    for frame in frames {
+        let raster = Raster::draw(&frame, layout);
+        let gif_frame = gif::Frame {
+            width,
+            height,
+            delay: (delay.as_millis() / 10) as u16,
+            buffer: raster.pixels.into(),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&gif_frame).map_err(export_error)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn write_pngs(frames: impl Iterator<Item = Frame>, dir: &Path, layout: &Layout) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let mut count = 0;
+    for frame in frames {
+        let raster = Raster::draw(&frame, layout);
+        let file = BufWriter::new(File::create(dir.join(format!("frame_{:05}.png", count)))?);
+        let (width, height) = layout.size()?;
+        let mut encoder = png::Encoder::new(file, width.into(), height.into());
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(PALETTE.concat());
+        encoder.write_header()?.write_image_data(&raster.pixels)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn svg_color(color: u8) -> String {
+    let [r, g, b] = PALETTE[color as usize];
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Lay the frames out in a square grid, one `<g id="frame-N">` per frame.
+fn write_svg(frames: impl Iterator<Item = Frame>, mut output: impl Write, layout: &Layout) -> io::Result<usize> {
+    let frames = frames.collect::<Vec<_>>();
+    let columns = (frames.len() as f64).sqrt().ceil().max(1.0) as usize;
+    let rows = frames.len().div_ceil(columns);
+    let gap = layout.cell;
+    let (sheet_width, sheet_height) = (
+        columns * (layout.width + gap) + gap,
+        rows * (layout.height + gap) + gap,
+    );
+    writeln!(
+        output,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        sheet_width, sheet_height
+    )?;
+    writeln!(output, r#"<rect width="100%" height="100%" fill="{}"/>"#, svg_color(BACKGROUND))?;
+    for (idx, frame) in frames.iter().enumerate() {
+        let (x, y) = (
+            gap + idx % columns * (layout.width + gap),
+            gap + idx / columns * (layout.height + gap),
+        );
+        writeln!(output, r#"<g id="frame-{}" transform="translate({},{})">"#, idx, x, y)?;
+        for rect in layout.rects(frame) {
+            writeln!(
+                output,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                rect.x,
+                rect.y,
+                rect.width,
+                rect.height,
+                svg_color(rect.color)
+            )?;
+        }
+        for (x, text) in layout.overlay(frame).into_iter().flatten() {
+            writeln!(
+                output,
+                r#"<text x="{}" y="{}" font-family="monospace" font-size="{}" fill="{}">{}</text>"#,
+                x,
+                6 * layout.font_scale,
+                6 * layout.font_scale,
+                svg_color(TEXT),
+                text
+            )?;
+        }
+        writeln!(output, "</g>")?;
+    }
+    writeln!(output, "</svg>")?;
+    output.flush()?;
+    Ok(frames.len())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{
+        game::{greedy_playout, GameState},
+        op::GameOP,
+        replay::{FrameStep, Replay},
+    };
+
+    use super::{brick_colors, export, sample, ExportFormat, ExportOptions, Layout, Raster, BRICK, PALETTE, TEXT};
+
+    fn ops(bricks: usize) -> Vec<GameOP> {
+        let mut ops = greedy_playout(&GameState::initial_state(), bricks).get_op_sequence();
+        ops.push(GameOP::New);
+        ops
+    }
+
+    #[test]
+    fn test_sample() {
+        let frames = Replay::new(ops(6), FrameStep::PerBrick).unwrap();
+        let counts = sample(frames, 4).map(|frame| frame.brick_count).collect::<Vec<_>>();
+        assert_eq!(counts, vec![0, 4, 6]);
+    }
+
+    #[test]
+    fn test_raster() {
+        let options = ExportOptions::default();
+        let layout = Layout::new(&options);
+        let frame = Replay::new(ops(3), FrameStep::PerBrick).unwrap().last().unwrap();
+        let raster = Raster::draw(&frame, &layout);
+        assert_eq!(raster.pixels.len(), layout.width * layout.height);
+        // The brick count 3 is drawn at the right of the overlay.
+        let overlay = &raster.pixels[..layout.overlay_height * layout.width];
+        assert!(overlay.contains(&TEXT));
+        let no_overlay = Layout::new(&ExportOptions {
+            score_overlay: false,
+            ..options
+        });
+        assert_eq!(no_overlay.height, 20 * no_overlay.cell);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let options = ExportOptions {
+            cell_size: 5000,
+            ..ExportOptions::default()
+        };
+        assert!(Layout::new(&options).size().is_err());
+        let path = std::env::temp_dir().join(format!("tetris_export_{}_large.gif", std::process::id()));
+        let err = export(ops(1), path.to_str().unwrap(), &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+
+        assert_eq!(brick_colors(&PALETTE).unwrap(), PALETTE.len() - BRICK as usize);
+        assert!(brick_colors(&PALETTE[..BRICK as usize]).is_err());
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let dir = std::env::temp_dir().join(format!("tetris_export_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let gif = path("replay.gif");
+        assert_eq!(ExportFormat::from_path(&gif), ExportFormat::Gif);
+        let count = export(ops(4), &gif, &ExportOptions::default()).unwrap();
+        assert_eq!(count, 5);
+        assert!(fs::read(&gif).unwrap().starts_with(b"GIF89a"));
+
+        let svg = path("replay.svg");
+        let options = ExportOptions {
+            format: ExportFormat::Svg,
+            step: FrameStep::PerOp,
+            skip: 2,
+            ..ExportOptions::default()
+        };
+        let count = export(ops(4), &svg, &options).unwrap();
+        let text = fs::read_to_string(&svg).unwrap();
+        assert!(text.starts_with("<svg"));
+        assert_eq!(text.matches("<g id=\"frame-").count(), count);
+
+        let pngs = path("frames");
+        let options = ExportOptions {
+            format: ExportFormat::PngDir,
+            ..ExportOptions::default()
+        };
+        assert_eq!(export(ops(2), &pngs, &options).unwrap(), 3);
+        let frame = fs::read(dir.join("frames").join("frame_00002.png")).unwrap();
+        assert!(frame.starts_with(b"\x89PNG"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,152 +1,200 @@
-use std::{ mem::{self}, slice::Iter};
+use std::{cmp::Ordering, slice::Iter, vec::{self, Drain}};
 
-pub struct FixedHeap<T, const N: usize> {
+/// Orders the elements of a `FixedHeap`, the smallest is evicted first.
+pub type Compare<T> = fn(&T, &T) -> Ordering;
+
+/// Keeps the `capacity` largest elements pushed into it.
+///
+/// It is a binary min-heap under `compare`, so the smallest kept element is
+/// at the root and is the one replaced when a larger element comes in.
+pub struct FixedHeap<T, F = Compare<T>> {
     data: Vec<T>,
-    actual_len: usize,
+    capacity: usize,
+    compare: F,
 }
 
-impl<T, const N: usize> FixedHeap<T, N> {
+impl<T: PartialOrd> FixedHeap<T> {
+    /// A heap ordered by `PartialOrd`, incomparable elements count as equal.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_comparator(capacity, |a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+    }
+}
+
+impl<T> FixedHeap<T> {
+    /// A heap ordered by the key `key` extracts from each element.
+    pub fn by_key<K: Ord>(capacity: usize, key: fn(&T) -> K) -> FixedHeap<T, impl Fn(&T, &T) -> Ordering> {
+        FixedHeap::with_comparator(capacity, move |a: &T, b: &T| key(a).cmp(&key(b)))
+    }
+}
+
+impl<T, F> FixedHeap<T, F> {
     pub fn clear(&mut self) {
-        self.actual_len = 0;
+        self.data.clear();
+    }
+    /// The elements in heap order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.data.iter()
+    }
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
     }
-    pub fn iter(&self) -> Iter<T> {
-        (&self.data[..self.actual_len]).iter()
+    /// Move all elements out in heap order, leaving the heap empty but with its
+    /// allocation kept for reuse.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        self.data.drain(..)
     }
     pub fn len(&self) -> usize {
-        self.actual_len
+        self.data.len()
     }
-    pub fn peak(&self) -> Option<&T> {
-        match self.actual_len {
-            0 => None,
-            _ => Some(&self.data[0]),
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn is_full(&self) -> bool {
+        self.data.len() >= self.capacity
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// The smallest element.
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+    /// The element a new one has to be larger than to be kept, `None` while
+    /// the heap is not full and takes anything. Lets callers skip building
+    /// elements that would be rejected.
+    pub fn min_threshold(&self) -> Option<&T> {
+        match self.is_full() {
+            true => self.data.first(),
+            false => None,
         }
     }
 }
 
-impl<T, const N: usize> Default for FixedHeap<T, N> where T : Default {
-    fn default() -> Self {
-        // let mut array: [T; N] = unsafe { MaybeUninit::uninit().assume_init() };
-        // for item in &mut array {
-        //     *item = T::default();
-        // }
+impl<T, F: Fn(&T, &T) -> Ordering> FixedHeap<T, F> {
+    pub fn with_comparator(capacity: usize, compare: F) -> Self {
         Self {
-            data: Vec::with_capacity(N),
-            actual_len: 0,
+            data: Vec::new(),
+            capacity,
+            compare,
         }
     }
-}
 
-impl<T, const N: usize> FixedHeap<T, N> where T : Copy + Default {
-    #[allow(dead_code)]
-    fn new() -> Self {
-        Self {
-            data: Vec::with_capacity(N),
-            actual_len: 0,
+    fn less(&self, a: usize, b: usize) -> bool {
+        (self.compare)(&self.data[a], &self.data[b]) == Ordering::Less
+    }
+
+    /// Keep at most `capacity` elements, the smallest ones are dropped if
+    /// there are more.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.data.len() > capacity {
+            self.pop();
         }
     }
-}
 
-impl<T, const N: usize> FixedHeap<T, N> where T : PartialOrd {
+    /// Add `element` if there is room or it is larger than the smallest one.
+    /// Returns the element that was not kept: the evicted smallest one, or
+    /// `element` itself.
     pub fn push(&mut self, element: T) -> Option<T> {
-        
-        if self.actual_len == N {
-            Some(self.replace_root(element))
-        } else {
-            self.push_back(element);
-            None
+        if !self.is_full() {
+            self.data.push(element);
+            self.sift_up(self.data.len() - 1);
+            return None;
+        }
+        match self.data.first() {
+            Some(min) if (self.compare)(&element, min) == Ordering::Greater => {
+                let evicted = std::mem::replace(&mut self.data[0], element);
+                self.sift_down(0);
+                Some(evicted)
+            }
+            _ => Some(element),
         }
     }
-    fn push_back(&mut self, element: T) {
-        if self.actual_len == self.data.len() {
-            self.data.push(element);
-        } else {
-            self.data[self.actual_len] = element;
+
+    /// The largest element. The heap only orders its root, so this scans
+    /// every element.
+    pub fn max(&self) -> Option<&T> {
+        self.data.iter().max_by(|a, b| (self.compare)(a, b))
+    }
+
+    /// Remove and return the smallest element.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
         }
-        let mut pos = self.actual_len;
-        self.actual_len += 1;
+        let min = self.data.swap_remove(0);
+        self.sift_down(0);
+        Some(min)
+    }
 
+    /// Keep only the elements `keep` returns true for.
+    pub fn retain(&mut self, keep: impl FnMut(&T) -> bool) {
+        self.data.retain(keep);
+        for pos in (0..self.data.len() / 2).rev() {
+            self.sift_down(pos);
+        }
+    }
+
+    /// The elements in ascending order.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let compare = &self.compare;
+        self.data.sort_by(|a, b| compare(a, b));
+        self.data
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
         while pos > 0 {
             let parent = (pos - 1) / 2;
-            if &self.data[parent] > &self.data[pos] {
-                self.data.swap(pos, parent);
+            if !self.less(pos, parent) {
+                break;
             }
+            self.data.swap(pos, parent);
             pos = parent;
         }
     }
 
-    fn replace_root(&mut self, mut element: T) -> T {
-        if self.actual_len == 0 || element < self.data[0] {
-            return element;
-        }
-        mem::swap(&mut self.data[0], &mut element);
-
-        let mut pos = 0;
-        while pos < self.actual_len {
-            let left_child_idx = pos * 2 + 1;
-            let mut right_child_idx = pos * 2 + 2;
-            if left_child_idx >= self.actual_len {
-                break;
-            } else if right_child_idx >= self.actual_len {
-                right_child_idx = left_child_idx;
+    fn sift_down(&mut self, mut pos: usize) {
+        loop {
+            let left = pos * 2 + 1;
+            let right = left + 1;
+            let mut smallest = pos;
+            if left < self.data.len() && self.less(left, smallest) {
+                smallest = left;
+            }
+            if right < self.data.len() && self.less(right, smallest) {
+                smallest = right;
             }
-            if self.data[left_child_idx] < self.data[pos] && self.data[left_child_idx] < self.data[right_child_idx] {
-                self.data.swap(pos, left_child_idx);
-                pos = left_child_idx;
-            } else if self.data[left_child_idx] < self.data[pos] || self.data[right_child_idx] <= self.data[pos] {
-                self.data.swap(pos, right_child_idx);
-                pos = right_child_idx;
-            } else {
+            if smallest == pos {
                 break;
             }
-         }
-
-        element
+            self.data.swap(pos, smallest);
+            pos = smallest;
+        }
     }
 }
 
-impl<T, const N: usize> IntoIterator for FixedHeap<T, N> {
-    type IntoIter = IntoIter<T, N>;
+impl<T, F> IntoIterator for FixedHeap<T, F> {
+    type IntoIter = vec::IntoIter<T>;
     type Item = T;
     fn into_iter(self) -> Self::IntoIter {
-        IntoIter {
-            data: self.data,
-            actual_len: self.actual_len,
-            current: 0
-        }
+        self.data.into_iter()
     }
 }
 
-impl<'a, T, const N: usize> IntoIterator for &'a FixedHeap<T, N> {
+impl<'a, T, F> IntoIterator for &'a FixedHeap<T, F> {
     type IntoIter = Iter<'a, T>;
     type Item = &'a T;
     fn into_iter(self) -> Self::IntoIter {
-        (&self.data[..self.actual_len]).into_iter()
-    }
-}
-
-pub struct IntoIter<T, const N: usize> {
-    data: Vec<T>,
-    current: usize,
-    actual_len: usize,
-}
-
-impl<T, const N: usize> Iterator for IntoIter<T, N> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.current >= self.actual_len {
-            return None;
-        }
-        let element = mem::replace(
-            &mut self.data[self.current], 
-            unsafe {mem::MaybeUninit::uninit().assume_init()}
-        );
-        self.current += 1;
-        Some(element)
+        self.data.iter()
     }
 }
 
 #[cfg(test)]
 mod test{
+    use std::cmp::Reverse;
+
+    use proptest::prelude::*;
+
     use super::*;
     use rand::Fill;
 
@@ -160,7 +208,7 @@ mod test{
         data.try_fill(&mut rng).unwrap();
 
         println!("{:?}", data);
-        let mut heap = FixedHeap::<DataType, HEAP_SIZE>::new();
+        let mut heap = FixedHeap::<DataType>::new(HEAP_SIZE);
         for x in data {
             heap.push(x);
         }
@@ -171,4 +219,82 @@ mod test{
         println!("{:?}", data);
         assert_eq!(&data[(DATA_SIZE - HEAP_SIZE)..], &heap_data[..HEAP_SIZE]);
     }
-}
\ No newline at end of file
+
+    /// The `capacity` largest of `values`, ascending.
+    fn model(values: &[i32], capacity: usize) -> Vec<i32> {
+        let mut sorted = values.to_vec();
+        sorted.sort();
+        sorted.split_off(sorted.len().saturating_sub(capacity))
+    }
+
+    fn filled(values: &[i32], capacity: usize) -> FixedHeap<i32> {
+        let mut heap = FixedHeap::new(capacity);
+        for value in values {
+            heap.push(*value);
+        }
+        heap
+    }
+
+    fn is_heap<T, F: Fn(&T, &T) -> Ordering>(heap: &FixedHeap<T, F>) -> bool {
+        (1..heap.len()).all(|pos| !heap.less(pos, (pos - 1) / 2))
+    }
+
+    proptest! {
+        #[test]
+        fn keeps_the_largest(values in prop::collection::vec(-50..50i32, 0..200), capacity in 0..40usize) {
+            let mut heap = FixedHeap::new(capacity);
+            let mut rejected = Vec::new();
+            for value in &values {
+                rejected.extend(heap.push(*value));
+                prop_assert!(is_heap(&heap));
+            }
+            let kept = model(&values, capacity);
+            prop_assert_eq!(heap.min_threshold().copied(), kept.first().copied().filter(|_| kept.len() == capacity));
+            prop_assert_eq!(heap.peek().copied(), kept.first().copied());
+            prop_assert_eq!(heap.max().copied(), kept.last().copied());
+            // Everything that was pushed out is no larger than what was kept.
+            prop_assert!(rejected.iter().all(|value| kept.first().is_none_or(|min| value <= min)));
+            prop_assert_eq!(rejected.len() + kept.len(), values.len());
+            prop_assert_eq!(heap.into_sorted_vec(), kept);
+        }
+
+        #[test]
+        fn retain_and_shrink(values in prop::collection::vec(-50..50i32, 0..200), capacity in 0..40usize, shrunk in 0..40usize) {
+            let mut heap = filled(&values, capacity);
+            heap.retain(|value| value % 3 != 0);
+            prop_assert!(is_heap(&heap));
+            let mut kept = model(&values, capacity);
+            kept.retain(|value| value % 3 != 0);
+
+            heap.set_capacity(shrunk);
+            prop_assert!(is_heap(&heap));
+            let kept = model(&kept, shrunk);
+            prop_assert_eq!(heap.into_sorted_vec(), kept);
+        }
+
+        #[test]
+        fn drain_empties(values in prop::collection::vec(-50..50i32, 0..200), capacity in 0..40usize) {
+            let mut heap = filled(&values, capacity);
+            let mut drained = heap.drain().collect::<Vec<_>>();
+            drained.sort();
+            prop_assert!(heap.is_empty());
+            prop_assert_eq!(drained, model(&values, capacity));
+            prop_assert_eq!(heap.push(1).is_none(), capacity > 0);
+            prop_assert_eq!(heap.len(), capacity.min(1));
+        }
+
+        #[test]
+        fn orders_by_key(values in prop::collection::vec(-50..50i32, 0..200), capacity in 0..40usize) {
+            let mut heap = FixedHeap::by_key(capacity, |value: &i32| Reverse(*value));
+            for value in &values {
+                heap.push(*value);
+            }
+            // Keyed by `Reverse`, the heap keeps the smallest values.
+            let mut kept = values.iter().map(|value| Reverse(*value)).collect::<Vec<_>>();
+            kept.sort();
+            let kept = kept.split_off(kept.len().saturating_sub(capacity));
+            let sorted = heap.into_sorted_vec().into_iter().map(Reverse).collect::<Vec<_>>();
+            prop_assert_eq!(sorted, kept);
+        }
+    }
+}
@@ -1,21 +1,49 @@
 
+use std::{cmp::Reverse, str::FromStr};
+
 use num::range_step_inclusive;
 
-use crate::{brick::{Brick}, grid::GameGrids, op::{GameOP}, random::{self, RANDOM_SEED, get_random_num}, vec2::{Vec2}};
+use crate::{brick::{Brick}, grid::{GameGrids, GridUndo, GRID_HEIGHT}, op::{GameOP}, random::{RANDOM_SEED, get_random_num}, vec2::{Vec2}};
 
 pub const INITIAL_POS: Vec2 = Vec2(4, 0);
 pub const MAX_BRICKS_COUNT: usize = 10000;
+/// Over the last bricks the heuristic terms of `sp_score` fade out linearly,
+/// cells left on the board when the game ends earn nothing.
+pub const ENDGAME_WINDOW: usize = 50;
+/// Most placements a brick can have, a T, L or J brick on a flat board.
+pub const MAX_PLACEMENTS: usize = 34;
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GameState {
     pub grids: GameGrids,
     pub score: u32,
     pub sp_score: i32,
     pub rand_num: i32,
+    /// The placements so far, packed by `encode_placement`.
     pub brick_stack: Vec<u16>,
     pub brick_count: usize,
 }
 
+/// A placement of the next brick scored on a copy of the board, so the child
+/// `GameState` only has to be built if the placement is kept.
+#[derive(Clone, Default)]
+pub struct Candidate {
+    pub placement: u16,
+    pub grids: GameGrids,
+    pub score: u32,
+    pub sp_score: i32,
+}
+
+/// What `GameState::make` changed, for `GameState::unmake`.
+#[derive(Clone, Copy, Default)]
+pub struct StateUndo {
+    pub grids: GridUndo,
+    score: u32,
+    sp_score: i32,
+    rand_num: i32,
+}
+
 impl GameState {
     pub fn initial_state() -> Self {
         Self {
@@ -28,41 +56,93 @@ impl GameState {
         }
     }
     pub fn next(&self, next_states: &mut [GameState]) -> usize {
-        let next_rand_num = random::get_random_num(self.rand_num);
-        let initial_brick = Brick::from_random_num(next_rand_num, self.brick_count);
-        let mut next_count = 0;
-        for y in (0i8..20).rev() {
-            for x in 0i8..10 {
-                let pos = Vec2(x, y);
-                if self.grids.get(pos) {
-                    continue;
-                }
+        let mut candidates: [Candidate; MAX_PLACEMENTS] = array_init::array_init(|_| Candidate::default());
+        let limit = next_states.len().min(MAX_PLACEMENTS);
+        let len = self.candidates(&mut candidates[..limit]);
+        for (candidate, next_state) in candidates[..len].iter().zip(next_states.iter_mut()) {
+            self.child_into(candidate, next_state);
+        }
+        len
+    }
 
-                for rot in 0..initial_brick.state_count() {
+    /// Score every placement of the next brick into `candidates`, without
+    /// building the children. Returns how many were written.
+    pub fn candidates(&self, candidates: &mut [Candidate]) -> usize {
+        let mut placements = [(Vec2(0, 0), 0); MAX_PLACEMENTS];
+        let len = self.placements(&mut placements);
+        let len = len.min(candidates.len());
+        for ((pos, rot), candidate) in placements[..len].iter().zip(candidates.iter_mut()) {
+            let brick = Brick::from_random_num(get_random_num(self.rand_num), self.brick_count).rotate_n(*rot);
+            let mut grids = self.grids.clone();
+            grids.place_teris_brick(&brick, *pos);
+            let (score, sp_score) = evaluate(&mut grids, self.score, self.brick_count + 1);
+            *candidate = Candidate {
+                placement: encode_placement(*pos, *rot),
+                grids,
+                score,
+                sp_score,
+            };
+        }
+        len
+    }
 
-                    let rotated_brick = initial_brick.rotate_n(rot);
-                    if !self.grids.can_place_brick(&rotated_brick, pos) {
-                        continue;
-                    }
-                    if rotated_brick.get_top_pos(pos.1) == 0 {
-                        continue;
-                    }
+    /// Every position and rotation count the next brick can be dropped to,
+    /// bottom rows first, then by column and rotation. A placement is reached
+    /// by moving sideways on the spawn row, rotating there and dropping, and
+    /// has to leave the top row empty. Returns how many were written.
+    pub fn placements(&self, placements: &mut [(Vec2, usize); MAX_PLACEMENTS]) -> usize {
+        let initial_brick = Brick::from_random_num(get_random_num(self.rand_num), self.brick_count);
+        // The board seen by a brick whose top row is `top`, for `top` in -2..=20.
+        let windows: [u64; 23] = array_init::array_init(|idx| self.grids.rows_window(idx as i8 - 2));
+        let collides = |brick: &Brick, pos: Vec2| {
+            let mask = brick.row_mask(pos.0);
+            mask == 0 || windows[(brick.get_top_pos(pos.1) + 2) as usize] & mask != 0
+        };
 
-                    let mut brick = initial_brick;
-                    if self.find_way(&mut brick, rot, pos) {
-                        let next_state = &mut next_states[next_count];
-                        next_state.clone_from(self);
-                        next_state.place_brick(&rotated_brick, pos, rot);
+        if collides(&initial_brick, INITIAL_POS) {
+            return 0;
+        }
+        let mut left = INITIAL_POS.0;
+        while !collides(&initial_brick, Vec2(left - 1, 0)) {
+            left -= 1;
+        }
+        let mut right = INITIAL_POS.0;
+        while !collides(&initial_brick, Vec2(right + 1, 0)) {
+            right += 1;
+        }
 
-                        next_count += 1;
-                        if next_count >= next_states.len() {
-                            return next_count;
-                        }
-                    }
+        let mut len = 0;
+        for x in left..=right {
+            for rot in 0..initial_brick.state_count() {
+                let brick = initial_brick.rotate_n(rot);
+                if collides(&brick, Vec2(x, 0)) {
+                    // Every further rotation passes this one.
+                    break;
+                }
+                let mut y = 0;
+                while !collides(&brick, Vec2(x, y + 1)) {
+                    y += 1;
+                }
+                if brick.get_top_pos(y) > 0 {
+                    placements[len] = (Vec2(x, y), rot);
+                    len += 1;
                 }
             }
         }
-        next_count
+        placements[..len].sort_unstable_by_key(|(pos, rot)| (Reverse(pos.1), pos.0, *rot));
+        len
+    }
+
+    /// Turn `child` into the child of `self` reached by `candidate`, reusing
+    /// the allocation of its `brick_stack`.
+    pub fn child_into(&self, candidate: &Candidate, child: &mut GameState) {
+        child.clone_from(self);
+        child.rand_num = get_random_num(self.rand_num);
+        child.brick_count += 1;
+        child.grids = candidate.grids.clone();
+        child.score = candidate.score;
+        child.sp_score = candidate.sp_score;
+        child.brick_stack.push(candidate.placement);
     }
 
     pub fn find_way(&self, brick: &mut Brick, rotations: usize, pos: Vec2) -> bool {
@@ -109,38 +189,9 @@ impl GameState {
     }
 
     pub fn evaluate_score(&mut self) {
-        let mut count = 0;
-        let mut occupied_blocks_count = 0;
-        let mut density = 0f32;
-        let mut height = 0;
-        let mut top_reached = false;
-        for row in 0..20 {
-            let blocks = self.grids.blocks_in_row(row);
-            occupied_blocks_count += blocks;
-            density = density + blocks as f32;
-            if !top_reached && self.grids.get_row(row) != 0 {
-                top_reached = true;
-                height = 20 - row;
-            }
-            if self.grids.is_full_row(row) {
-                count += 1;
-                self.grids.remove_row(row);
-            }
-        }
-        density /= height as f32 * 10f32;
-        let terris_score = match count {
-            1 => occupied_blocks_count * 1,
-            2 => occupied_blocks_count * 3,
-            3 => occupied_blocks_count * 6,
-            4 => occupied_blocks_count * 10,
-            _ => 0,
-        };
-        self.score += terris_score as u32;
-        self.sp_score = self.score as i32 
-            - (height as i32 - 17).abs() * 1
-            - (height as i32 - 17).clamp(0, 10).pow(4) * 3
-            + (occupied_blocks_count as i32) * 14
-            + (density * 200f32) as i32;
+        let (score, sp_score) = evaluate(&mut self.grids, self.score, self.brick_count);
+        self.score = score;
+        self.sp_score = sp_score;
     }
 
     pub fn next_brick(&mut self) -> Brick {
@@ -165,37 +216,268 @@ impl GameState {
         self.grids.place_teris_brick(brick, pos);
         self.next_brick();
         self.evaluate_score();
-        self.brick_stack.push((pos.0 as u16) | (pos.1 as u16) << 4 | (rot as u16) << 10);
+        self.brick_stack.push(encode_placement(pos, rot));
+    }
+
+    /// Rebuild a state by replaying the placements recorded in a `brick_stack`.
+    pub fn from_brick_stack(brick_stack: &[u16]) -> Self {
+        let mut state = Self::initial_state();
+        for placement in brick_stack {
+            state.apply_placement(*placement);
+        }
+        state
+    }
+
+    /// `from_brick_stack` for untrusted input: every entry has to be one of
+    /// the `placements` of its brick, and there can't be more than
+    /// `MAX_BRICKS_COUNT` of them.
+    pub fn try_from_brick_stack(brick_stack: &[u16]) -> Result<Self, String> {
+        if brick_stack.len() > MAX_BRICKS_COUNT {
+            return Err(format!("{} placements, at most {}", brick_stack.len(), MAX_BRICKS_COUNT));
+        }
+        let mut state = Self::initial_state();
+        let mut placements = [(Vec2(0, 0), 0); MAX_PLACEMENTS];
+        for (idx, placement) in brick_stack.iter().enumerate() {
+            let len = state.placements(&mut placements);
+            if !placements[..len].contains(&decode_placement(*placement)) {
+                return Err(format!("invalid placement {:04x} of brick {}", placement, idx));
+            }
+            state.apply_placement(*placement);
+        }
+        Ok(state)
+    }
+
+    /// Place the next brick as described by a `brick_stack` entry.
+    pub fn apply_placement(&mut self, placement: u16) {
+        let (pos, rot) = decode_placement(placement);
+        let brick = Brick::from_random_num(get_random_num(self.rand_num), self.brick_count);
+        self.place_brick(&brick.rotate_n(rot), pos, rot);
+    }
+
+    /// `apply_placement` in place, returning what `unmake` needs to go back
+    /// to this state. Allocates nothing once `brick_stack` has room.
+    pub fn make(&mut self, placement: u16) -> StateUndo {
+        let (pos, rot) = decode_placement(placement);
+        let mut undo = StateUndo {
+            grids: GridUndo::default(),
+            score: self.score,
+            sp_score: self.sp_score,
+            rand_num: self.rand_num,
+        };
+        let brick = self.next_brick().rotate_n(rot);
+        undo.grids = self.grids.make(&brick, pos);
+        // States have no full rows, so the brick leaves at most four.
+        let (score, sp_score) = evaluate_undoable(&mut self.grids, self.score, self.brick_count, &mut undo.grids)
+            .expect("more than four full rows");
+        self.score = score;
+        self.sp_score = sp_score;
+        self.brick_stack.push(placement);
+        undo
+    }
+
+    /// Take back the `make` that returned `undo`, which has to be the last one.
+    pub fn unmake(&mut self, undo: &StateUndo) {
+        self.grids.unmake(&undo.grids);
+        self.score = undo.score;
+        self.sp_score = undo.sp_score;
+        self.rand_num = undo.rand_num;
+        self.brick_count -= 1;
+        self.brick_stack.pop();
+    }
+
+    /// Parse a state written by `to_ascii`: `score`, `brick_count` and
+    /// `rand_num` lines followed by the board, see `GameGrids::from_ascii`.
+    /// The placements that led to the board are unknown, so `brick_stack`
+    /// starts empty, and `sp_score` is measured on the board as it stands.
+    /// Full rows are rejected, the game clears them as soon as they fill.
+    pub fn from_ascii(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let score = ascii_field(lines.next(), "score")?;
+        let brick_count = ascii_field(lines.next(), "brick_count")?;
+        let rand_num = ascii_field(lines.next(), "rand_num")?;
+        let grids = GameGrids::from_ascii(&lines.collect::<Vec<_>>().join("\n"))?;
+        if let Some(row) = (0..GRID_HEIGHT as i8).find(|row| grids.is_full_row(*row)) {
+            return Err(format!("row {} is full, a board can't have full rows", row));
+        }
+        let features = Features::measure(&grids, brick_count);
+        Ok(Self {
+            sp_score: features.sp_score(score, brick_count),
+            grids,
+            score,
+            rand_num,
+            brick_stack: Vec::with_capacity(MAX_BRICKS_COUNT),
+            brick_count,
+        })
+    }
+
+    /// The `score`, `brick_count`, `rand_num` and board of the state as text.
+    pub fn to_ascii(&self) -> String {
+        format!(
+            "score {}\nbrick_count {}\nrand_num {}\n{}",
+            self.score,
+            self.brick_count,
+            self.rand_num,
+            self.grids.to_ascii()
+        )
     }
 
     pub fn get_op_sequence(&self) -> Vec<GameOP> {
         let mut ops = Vec::with_capacity(MAX_BRICKS_COUNT * 3);
         let mut ghost = GameState::initial_state();
         for state in &self.brick_stack {
-            let pos = Vec2((state & 0b1111) as i8, ((state & 0b111110000) >> 4) as i8);
-            let rot = (state & 0b1111_0000000000) >> 10;
-            
             ghost.next_brick();
-            let diff = pos - INITIAL_POS;
-            ops.push(GameOP::New);
-            match diff.0 {
-                x if x < 0 => ops.push(GameOP::Left(-x)),
-                x if x > 0 => ops.push(GameOP::Right(x)),
-                _ => (),
-            }
-            if rot > 0 {
-                ops.push(GameOP::Rotate(rot as i8));
+            push_placement_ops(&mut ops, *state);
+        }
+
+        ops
+    }
+}
+
+/// Append the ops that spawn a brick and move it to `placement`: sideways,
+/// then rotate, then down, the way `GameState::placements` reaches it.
+pub fn push_placement_ops(ops: &mut Vec<GameOP>, placement: u16) {
+    let (pos, rot) = decode_placement(placement);
+    let diff = pos - INITIAL_POS;
+    ops.push(GameOP::New);
+    match diff.0 {
+        x if x < 0 => ops.push(GameOP::Left(-x)),
+        x if x > 0 => ops.push(GameOP::Right(x)),
+        _ => (),
+    }
+    if rot > 0 {
+        ops.push(GameOP::Rotate(rot as i8));
+    }
+    if diff.1 > 0 {
+        ops.push(GameOP::Down(diff.1));
+    }
+}
+
+fn ascii_field<T: FromStr>(line: Option<&str>, name: &str) -> Result<T, String> {
+    let line = line.ok_or_else(|| format!("missing {}", name))?;
+    match line.split_once(' ') {
+        Some((key, value)) if key == name => value
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid {}: {}", name, value.trim())),
+        _ => Err(format!("expected {}, found: {}", name, line)),
+    }
+}
+
+/// Pack a placement into a `brick_stack` entry: x in bits 0..4, y in bits 4..9
+/// and the number of rotations in bits 10..14.
+pub fn encode_placement(pos: Vec2, rot: usize) -> u16 {
+    (pos.0 as u16) | (pos.1 as u16) << 4 | (rot as u16) << 10
+}
+
+pub fn decode_placement(placement: u16) -> (Vec2, usize) {
+    let pos = Vec2((placement & 0b1111) as i8, ((placement & 0b1_1111_0000) >> 4) as i8);
+    let rot = (placement & 0b11_1100_0000_0000) >> 10;
+    (pos, rot as usize)
+}
+
+/// Clear the full rows of `grids` after the `brick_count`-th brick was placed
+/// on it and return the new score and `sp_score`.
+pub fn evaluate(grids: &mut GameGrids, score: u32, brick_count: usize) -> (u32, i32) {
+    let features = Features::measure(grids, brick_count);
+    if features.cleared_rows > 0 {
+        grids.remove_full_rows();
+    }
+    let score = score + features.clear_score();
+    (score, features.sp_score(score, brick_count))
+}
+
+/// `evaluate` recording the cleared rows in `undo`, see
+/// `GameGrids::clear_full_rows` for when that fails.
+pub fn evaluate_undoable(
+    grids: &mut GameGrids,
+    score: u32,
+    brick_count: usize,
+    undo: &mut GridUndo,
+) -> Result<(u32, i32), String> {
+    let features = Features::measure(grids, brick_count);
+    if features.cleared_rows > 0 {
+        grids.clear_full_rows(undo)?;
+    }
+    let score = score + features.clear_score();
+    Ok((score, features.sp_score(score, brick_count)))
+}
+
+/// What `evaluate` measures on a board right after a brick was placed on it,
+/// before the full rows are cleared.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Features {
+    /// Rows from the bottom up to the highest occupied cell.
+    pub height: usize,
+    pub occupied_blocks: usize,
+    /// Share of the cells below `height` that are occupied.
+    pub density: f32,
+    pub cleared_rows: usize,
+}
+
+impl Features {
+    /// Measure `grids`, counting the full rows the placement of the
+    /// `brick_count`-th brick clears.
+    pub fn measure(grids: &GameGrids, brick_count: usize) -> Self {
+        // Like `update` in game.core.js, the brick that ends the game neither
+        // clears rows nor scores.
+        let round_limited = brick_count >= MAX_BRICKS_COUNT;
+        let mut features = Self::default();
+        let mut top_reached = false;
+        for row in 0..20 {
+            let blocks = grids.blocks_in_row(row);
+            features.occupied_blocks += blocks;
+            features.density += blocks as f32;
+            if !top_reached && grids.get_row(row) != 0 {
+                top_reached = true;
+                features.height = 20 - row as usize;
             }
-            if diff.1 > 0 {
-                ops.push(GameOP::Down(diff.1));
+            if !round_limited && grids.is_full_row(row) {
+                features.cleared_rows += 1;
             }
-
         }
+        features.density /= features.height as f32 * 10f32;
+        features
+    }
 
-        ops
+    /// Points for the cleared rows, which count every occupied cell.
+    pub fn clear_score(&self) -> u32 {
+        let multiplier = match self.cleared_rows {
+            1 => 1,
+            2 => 3,
+            3 => 6,
+            4 => 10,
+            _ => 0,
+        };
+        (self.occupied_blocks * multiplier) as u32
+    }
+
+    /// The weighted heuristic terms by name, they add up to `heuristic`.
+    pub fn terms(&self) -> [(&'static str, i32); 4] {
+        let height = self.height as i32;
+        [
+            ("height off 17", -(height - 17).abs()),
+            ("height above 17", -(height - 17).clamp(0, 10).pow(4) * 3),
+            ("occupied cells", self.occupied_blocks as i32 * 14),
+            ("density", (self.density * 200f32) as i32),
+        ]
+    }
+
+    pub fn heuristic(&self) -> i32 {
+        self.terms().iter().map(|(_, value)| value).sum()
+    }
+
+    /// `score` plus the heuristic, faded out over the last `ENDGAME_WINDOW`
+    /// bricks.
+    pub fn sp_score(&self, score: u32, brick_count: usize) -> i32 {
+        score as i32 + self.heuristic() * endgame_weight(brick_count) / ENDGAME_WINDOW as i32
     }
 }
 
+/// How many `ENDGAME_WINDOW`ths of the heuristic count after `brick_count` bricks.
+pub fn endgame_weight(brick_count: usize) -> i32 {
+    MAX_BRICKS_COUNT.saturating_sub(brick_count).min(ENDGAME_WINDOW) as i32
+}
+
 impl PartialEq for GameState {
     fn eq(&self, other: &Self) -> bool {
         self.sp_score == other.sp_score
@@ -208,14 +490,233 @@ impl PartialOrd for GameState {
     }    
 }
 
+/// Test fixture: play `bricks` more bricks from `state`, each time taking the
+/// child with the best `sp_score`. Stops early when no brick fits.
+#[cfg(test)]
+pub fn greedy_playout(state: &GameState, bricks: usize) -> GameState {
+    let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+    let mut state = state.clone();
+    for _ in 0..bricks {
+        let len = state.next(&mut next_states);
+        match next_states[..len].iter().max_by_key(|state| state.sp_score) {
+            Some(best) => state = best.clone(),
+            None => break,
+        }
+    }
+    state
+}
+
 #[cfg(test)]
 mod test {
     use std::mem::size_of;
 
-    use crate::game::GameState;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        brick::Brick,
+        game::{encode_placement, greedy_playout, Candidate, GameState, MAX_PLACEMENTS},
+        grid::GameGrids,
+        random::get_random_num,
+        vec2::Vec2,
+    };
+
+    /// A state from a picture of the lowest rows of its board, the rows above
+    /// are empty.
+    fn state(score: u32, brick_count: usize, rand_num: i32, picture: &str) -> GameState {
+        let rows = picture.lines().filter(|line| !line.trim().is_empty()).count();
+        let text = format!(
+            "score {}\nbrick_count {}\nrand_num {}\n{}{}",
+            score,
+            brick_count,
+            rand_num,
+            "..........\n".repeat(20 - rows),
+            picture
+        );
+        GameState::from_ascii(&text).unwrap()
+    }
 
     #[test]
     fn test() {
         assert!(size_of::<GameState>() * 800000 < 8 * 1024 * 1024 * 1024);
     }
+
+    #[test]
+    fn test_ascii() {
+        let state = greedy_playout(&GameState::initial_state(), 30);
+        let text = state.to_ascii();
+        let parsed = GameState::from_ascii(&text).unwrap();
+        assert_eq!(parsed.grids, state.grids);
+        assert_eq!((parsed.score, parsed.brick_count, parsed.rand_num), (state.score, 30, state.rand_num));
+        assert_eq!(parsed.to_ascii(), text);
+
+        let board = GameGrids::new().to_ascii();
+        assert_eq!(GameState::from_ascii(&board).err().unwrap(), "expected score, found: ..........");
+        let text = format!("score 10\nbrick_count 2\nrand_num x\n{}", board);
+        assert_eq!(GameState::from_ascii(&text).err().unwrap(), "invalid rand_num: x");
+        assert_eq!(GameState::from_ascii("score 10\n").err().unwrap(), "missing brick_count");
+
+        let rows = "..........\n".repeat(15) + &"##########\n".repeat(5);
+        let text = format!("score 10\nbrick_count 2\nrand_num 0\n{}", rows);
+        assert_eq!(GameState::from_ascii(&text).err().unwrap(), "row 15 is full, a board can't have full rows");
+    }
+
+    #[test]
+    fn test_evaluate_score() {
+        let mut state = state(100, 10, 0, "");
+        state.grids = GameGrids::from_ascii(&("..........\n".repeat(16) + "
+            ..#.......
+            ##########
+            #.########
+            ##########
+        ")).unwrap();
+        state.evaluate_score();
+        // 30 cells with 2 full rows score 30 * 3. The heuristic sees the board
+        // before the clear: -|4 - 17| + 30 * 14 + 0.75 * 200.
+        assert_eq!(state.score, 190);
+        assert_eq!(state.sp_score, 190 - 13 + 420 + 150);
+        assert_eq!(state.grids, self::state(0, 0, 0, "
+            ..#.......
+            #.########
+        ").grids);
+    }
+
+    #[test]
+    fn test_placements_on_board() {
+        // Bricks can't get past the tower, nor under the overhang.
+        let state = state(0, 7, 12345, &("..#.......\n".repeat(3) + &"..#....###\n".repeat(15) + "
+            ........##
+            #####.....
+        "));
+        let mut placements = [(Vec2(0, 0), 0); MAX_PLACEMENTS];
+        let len = state.placements(&mut placements);
+        assert!(len > 0);
+        assert_eq!(placements[..len], scalar_placements(&state)[..]);
+        assert!(placements[..len].iter().all(|(pos, _)| pos.0 > 1 && pos.1 < 19));
+    }
+
+    #[test]
+    fn test_candidates_match_children() {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let mut candidates = vec![Candidate::default(); MAX_PLACEMENTS];
+        let mut state = GameState::initial_state();
+        for _ in 0..30 {
+            let len = state.candidates(&mut candidates);
+            assert_eq!(state.next(&mut next_states), len);
+            for (candidate, child) in candidates[..len].iter().zip(&next_states) {
+                let mut placed = state.clone();
+                placed.apply_placement(candidate.placement);
+                assert_eq!((child.score, child.sp_score), (placed.score, placed.sp_score));
+                assert_eq!(child.grids, placed.grids);
+                assert_eq!(child.brick_stack, placed.brick_stack);
+                assert_eq!((child.rand_num, child.brick_count), (placed.rand_num, placed.brick_count));
+            }
+            state = next_states[..len].iter().max_by_key(|state| state.sp_score).unwrap().clone();
+        }
+        assert!(state.score > 0);
+    }
+
+    #[test]
+    fn test_try_from_brick_stack() {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let mut state = GameState::initial_state();
+        for _ in 0..20 {
+            let len = state.next(&mut next_states);
+            state = next_states[..len].iter().max_by_key(|state| state.sp_score).unwrap().clone();
+        }
+        let replayed = GameState::try_from_brick_stack(&state.brick_stack).unwrap();
+        assert_eq!((replayed.grids, replayed.score), (state.grids.clone(), state.score));
+
+        let mut brick_stack = state.brick_stack.clone();
+        brick_stack.push(0xffff);
+        assert_eq!(GameState::try_from_brick_stack(&brick_stack).err().unwrap(), "invalid placement ffff of brick 20");
+        // Floating in the air above the stack.
+        brick_stack[20] = encode_placement(Vec2(4, 2), 0);
+        assert!(GameState::try_from_brick_stack(&brick_stack).is_err());
+    }
+
+    #[test]
+    fn test_make_unmake() {
+        let mut candidates = vec![Candidate::default(); MAX_PLACEMENTS];
+        let mut state = GameState::initial_state();
+        let mut cleared = 0;
+        for _ in 0..60 {
+            let len = state.candidates(&mut candidates);
+            let before = state.clone();
+            for candidate in &candidates[..len] {
+                let mut placed = state.clone();
+                placed.apply_placement(candidate.placement);
+                let undo = state.make(candidate.placement);
+                assert_eq!((state.score, state.sp_score), (placed.score, placed.sp_score));
+                assert_eq!(state.grids, placed.grids);
+                assert_eq!(state.brick_stack, placed.brick_stack);
+                assert_eq!((state.rand_num, state.brick_count), (placed.rand_num, placed.brick_count));
+                cleared += undo.grids.cleared().len();
+
+                state.unmake(&undo);
+                assert_eq!((state.score, state.sp_score), (before.score, before.sp_score));
+                assert_eq!(state.grids, before.grids);
+                assert_eq!(state.brick_stack, before.brick_stack);
+                assert_eq!((state.rand_num, state.brick_count), (before.rand_num, before.brick_count));
+            }
+            match candidates[..len].iter().max_by_key(|candidate| candidate.sp_score) {
+                Some(best) => state.make(best.placement),
+                None => break,
+            };
+        }
+        assert!(cleared > 0);
+    }
+
+    /// The placements of the next brick found by testing cells one by one.
+    fn scalar_placements(state: &GameState) -> Vec<(Vec2, usize)> {
+        let fits = |brick: &Brick, center: Vec2, allow_outbound: bool| {
+            brick.get_pos().iter().all(|offset| match *offset + center {
+                pos @ Vec2(0..=9, 0..=19) => state.grids.is_empty(pos),
+                Vec2(0..=9, y) => allow_outbound && y < 0,
+                _ => false,
+            })
+        };
+        let initial_brick = Brick::from_random_num(get_random_num(state.rand_num), state.brick_count);
+        let mut placements = Vec::new();
+        for y in (0i8..20).rev() {
+            for x in 0i8..10 {
+                for rot in 0..initial_brick.state_count() {
+                    let brick = initial_brick.rotate_n(rot);
+                    let pos = Vec2(x, y);
+                    if !fits(&brick, pos, false) || brick.get_top_pos(y) == 0 {
+                        continue;
+                    }
+                    let rests = brick
+                        .get_lower_bound()
+                        .iter()
+                        .any(|bound| (*bound + pos).1 == 20 || state.grids.get(*bound + pos));
+                    let reachable = (x.min(4)..=x.max(4)).all(|x| fits(&initial_brick, Vec2(x, 0), true))
+                        && (1..=rot).all(|rot| fits(&initial_brick.rotate_n(rot), Vec2(x, 0), true))
+                        && (0..y).all(|y| fits(&brick, Vec2(x, y), true));
+                    if rests && reachable {
+                        placements.push((pos, rot));
+                    }
+                }
+            }
+        }
+        placements
+    }
+
+    #[test]
+    fn test_placements_match_cells() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut placements = [(Vec2(0, 0), 0); MAX_PLACEMENTS];
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        for _ in 0..20 {
+            let mut state = GameState::initial_state();
+            loop {
+                let len = state.placements(&mut placements);
+                assert_eq!(placements[..len], scalar_placements(&state)[..]);
+                let len = state.next(&mut next_states);
+                if len == 0 {
+                    break;
+                }
+                state = next_states[rng.gen_range(0..len)].clone();
+            }
+        }
+    }
 }
\ No newline at end of file
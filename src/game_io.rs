@@ -9,12 +9,74 @@ pub trait RenderGame {
     fn render_game(&mut self, state: &GameState);
     fn render_user_hint(&mut self);
     fn render_brick(&mut self, brick: &Brick, pos: Vec2);
+    /// Outline of where `brick` lands on a hard drop.
+    fn render_ghost(&mut self, brick: &Brick, pos: Vec2);
+    /// The placement suggested by a hint, drawn apart from the active brick.
+    fn render_hint(&mut self, brick: &Brick, pos: Vec2);
+    fn render_next_brick(&mut self, brick: &Brick);
+    fn render_message(&mut self, message: &str);
+    /// Replace the screen with `lines` of text.
+    fn render_lines(&mut self, lines: &[String]);
     fn flush(&mut self);
 }
 
+impl<R: RenderGame + ?Sized> RenderGame for Box<R> {
+    fn render_game(&mut self, state: &GameState) {
+        (**self).render_game(state)
+    }
+
+    fn render_user_hint(&mut self) {
+        (**self).render_user_hint()
+    }
+
+    fn render_brick(&mut self, brick: &Brick, pos: Vec2) {
+        (**self).render_brick(brick, pos)
+    }
+
+    fn render_ghost(&mut self, brick: &Brick, pos: Vec2) {
+        (**self).render_ghost(brick, pos)
+    }
+
+    fn render_hint(&mut self, brick: &Brick, pos: Vec2) {
+        (**self).render_hint(brick, pos)
+    }
+
+    fn render_next_brick(&mut self, brick: &Brick) {
+        (**self).render_next_brick(brick)
+    }
+
+    fn render_message(&mut self, message: &str) {
+        (**self).render_message(message)
+    }
+
+    fn render_lines(&mut self, lines: &[String]) {
+        (**self).render_lines(lines)
+    }
+
+    fn flush(&mut self) {
+        (**self).flush()
+    }
+}
+
+/// A key press in interactive play.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayInput {
+    Op(GameOP),
+    /// Drop the brick to the bottom and lock it.
+    Drop,
+    Undo,
+    Redo,
+    Save,
+    Load,
+    /// Ask the search for a placement of the current brick.
+    Hint,
+    Quit,
+}
+
 pub trait GetInput {
-    fn get_input(&mut self) -> GameOP;
+    fn get_input(&mut self) -> PlayInput;
 
+    #[allow(clippy::result_unit_err)]
     fn try_get_interrupt(&mut self) -> Result<(), ()>;
 }
 
@@ -25,9 +87,9 @@ mod unix_renderer {
 
     use termion::{clear, cursor, event::Key, input::{Keys, TermRead}, raw::{IntoRawMode, RawTerminal}};
 
-    use crate::{game::GameState, op::GameOP, vec2::Vec2};
+    use crate::{brick::Brick, game::GameState, op::GameOP, vec2::Vec2};
 
-    use super::{RenderGame, GetInput};
+    use super::{RenderGame, GetInput, PlayInput};
     pub struct UnixRenderer {
         stdout: RawTerminal<Stdout>,
     }
@@ -40,6 +102,25 @@ mod unix_renderer {
         }
     }
 
+    impl UnixRenderer {
+        fn render_cells(&mut self, brick: &Brick, pos: Vec2, cell: char) {
+            for i in 0..4 {
+                let pos = brick.get_pos()[i] + pos + Vec2(1, 1);
+                match pos {
+                    Vec2(0..=10, 0..=20) => (),
+                    _ => continue,
+                }
+                print!("{}{}", cursor::Goto(pos.0 as u16, pos.1 as u16), cell);
+            }
+        }
+    }
+
+    impl Default for UnixRenderer {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl RenderGame for UnixRenderer {
         
 
@@ -67,20 +148,44 @@ mod unix_renderer {
         fn render_user_hint(&mut self) {
             print!("{}<Left|Right|Down>: Move", cursor::Goto(13, 6));
             print!("{}<Up>: Rotate", cursor::Goto(13, 7));
-            print!("{}<Space>: Place", cursor::Goto(13, 8));
+            print!("{}<Space>: Drop", cursor::Goto(13, 8));
+            print!("{}<u|r>: Undo/Redo", cursor::Goto(13, 9));
+            print!("{}<s|l>: Save/Load", cursor::Goto(13, 10));
+            print!("{}<h>: Hint", cursor::Goto(13, 11));
+            print!("{}<q>: Quit", cursor::Goto(13, 12));
         }
 
-        fn render_brick(&mut self, brick: &crate::brick::Brick, pos:Vec2) {
-            for i in 0..4 {
-                let pos = brick.get_pos()[i] + pos + Vec2(1, 1);
-                match pos {
-                    Vec2(0..=10, 0..=20) => (),
-                    _ => continue,
-                }
+        fn render_brick(&mut self, brick: &Brick, pos:Vec2) {
+            self.render_cells(brick, pos, '*');
+        }
+
+        fn render_ghost(&mut self, brick: &Brick, pos: Vec2) {
+            self.render_cells(brick, pos, '.');
+        }
+
+        fn render_hint(&mut self, brick: &Brick, pos: Vec2) {
+            self.render_cells(brick, pos, '+');
+        }
+
+        fn render_next_brick(&mut self, brick: &Brick) {
+            print!("{}Next:", cursor::Goto(13, 14));
+            for offset in brick.get_pos() {
+                let pos = *offset + Vec2(16, 17);
                 print!("{}*", cursor::Goto(pos.0 as u16, pos.1 as u16));
             }
         }
 
+        fn render_message(&mut self, message: &str) {
+            print!("{}{}", cursor::Goto(13, 20), message);
+        }
+
+        fn render_lines(&mut self, lines: &[String]) {
+            print!("{}", clear::All);
+            for (y, line) in lines.iter().enumerate() {
+                print!("{}{}", cursor::Goto(1, y as u16 + 1), line);
+            }
+        }
+
         fn flush(&mut self) {
             self.stdout.flush().unwrap();
         }
@@ -88,11 +193,20 @@ mod unix_renderer {
 
     pub struct UnixInput {
         key: Keys<Stdin>,
-        int_receiver: Receiver<()>,
+        int_receiver: Option<Receiver<()>>,
     }
     
     impl UnixInput {
         pub fn new() -> Self {
+            Self {
+                key: stdin().keys(),
+                int_receiver: None,
+            }
+        }
+
+        /// The interrupt watcher consumes stdin, so it's only started when
+        /// interrupts are polled instead of keys read.
+        fn spawn_interrupt_watcher() -> Receiver<()> {
             let (sender, receiver) = channel();
 
             thread::spawn(move || {
@@ -104,24 +218,33 @@ mod unix_renderer {
                 }
             });
 
-            Self {
-                key: stdin().keys(),
-                int_receiver: receiver
-            }
+            receiver
+        }
+    }
+
+    impl Default for UnixInput {
+        fn default() -> Self {
+            Self::new()
         }
     }
 
     impl GetInput for UnixInput {
-        fn get_input(&mut self) -> crate::op::GameOP {
+        fn get_input(&mut self) -> PlayInput {
             loop {
                 if let Some(key) = self.key.next() {
                     break match key {
-                        Ok(Key::Left) => GameOP::Left(1),
-                        Ok(Key::Right) => GameOP::Right(1),
-                        Ok(Key::Down) => GameOP::Down(1),
-                        Ok(Key::Char(' ')) => GameOP::New,
-                        Ok(Key::Up) => GameOP::Rotate(1),
-                        Err(err) => Err(err).unwrap(),
+                        Ok(Key::Left) => PlayInput::Op(GameOP::Left(1)),
+                        Ok(Key::Right) => PlayInput::Op(GameOP::Right(1)),
+                        Ok(Key::Down) => PlayInput::Op(GameOP::Down(1)),
+                        Ok(Key::Up) => PlayInput::Op(GameOP::Rotate(1)),
+                        Ok(Key::Char(' ')) => PlayInput::Drop,
+                        Ok(Key::Char('u')) => PlayInput::Undo,
+                        Ok(Key::Char('r')) => PlayInput::Redo,
+                        Ok(Key::Char('s')) => PlayInput::Save,
+                        Ok(Key::Char('l')) => PlayInput::Load,
+                        Ok(Key::Char('h')) => PlayInput::Hint,
+                        Ok(Key::Char('q')) | Ok(Key::Ctrl('c')) => PlayInput::Quit,
+                        Err(err) => panic!("{}", err),
                         _ => continue,
                     }
                 }
@@ -129,7 +252,9 @@ mod unix_renderer {
             
         }
         fn try_get_interrupt(&mut self) -> Result<(), ()> {
-            match self.int_receiver.try_recv() {
+            match self.int_receiver
+                .get_or_insert_with(Self::spawn_interrupt_watcher)
+                .try_recv() {
                 Ok(_) => Ok(()),
                 Err(_) => Err(()),
             }
@@ -187,6 +312,26 @@ mod win_renderer {
             
         }
 
+        fn render_ghost(&mut self, brick: &crate::brick::Brick, pos: Vec2) {
+        }
+
+        fn render_hint(&mut self, brick: &crate::brick::Brick, pos: Vec2) {
+        }
+
+        fn render_next_brick(&mut self, brick: &crate::brick::Brick) {
+        }
+
+        fn render_message(&mut self, message: &str) {
+            println!("{}", message);
+        }
+
+        fn render_lines(&mut self, lines: &[String]) {
+            stdout().queue(terminal::Clear(ClearType::All));
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+
         fn flush(&mut self) {
             
         }
@@ -203,7 +348,7 @@ mod win_renderer {
     }
 
     impl super::GetInput for WinInput {
-        fn get_input(&mut self) -> crate::op::GameOP {
+        fn get_input(&mut self) -> super::PlayInput {
             loop {
                 let mut buf = [0u8; 1];
                 stdin().read(&mut buf).unwrap();
@@ -1,89 +1,662 @@
-use crate::{brick::Brick, game::{self, GameState}, game_io::{self, GetInput, RenderGame}, op::GameOP, vec2::{Vec2}};
-use crate::game_io::GameRenderer;
+use std::{fs, io, mem};
 
+use crate::{auto::{TetrisAuto, HINT_DEPTH, HINT_WIDTH}, brick::Brick, game::{self, GameState, StateUndo, MAX_BRICKS_COUNT}, game_io::{self, GetInput, PlayInput, RenderGame}, grid::{GameGrids, GRID_HEIGHT}, op::{self, GameOP, GameOPStr}, random::get_random_num, vec2::{Vec2}};
+
+/// Local game following the rules of game.core.js, the applied ops are
+/// recorded in the same coalesced format as the web game.
 pub struct Game {
     state: GameState,
     brick_pos: Vec2,
     brick: Brick,
-    renderer: GameRenderer,
+    ops: Vec<GameOP>,
+    message: String,
+    game_over: bool,
+    undo_history: Vec<Step>,
+    redo_history: Vec<Step>,
+    /// The placement of the brick locked last and its undo record, taken by
+    /// `with_undo`.
+    last_lock: Option<(u16, StateUndo)>,
+    hint: Option<Hint>,
+}
+
+/// Placement suggested by the search for the current brick.
+pub struct Hint {
+    pub brick: Brick,
+    pub pos: Vec2,
+    /// Ops that move the current brick there, `None` if there is no straight
+    /// path from where it is now.
+    pub ops: Option<Vec<GameOP>>,
+    pub sp_score: i32,
+    /// `sp_score` after a hard drop from the current position, `None` if the
+    /// brick would lock above the board.
+    pub intended_sp_score: Option<i32>,
+}
+
+/// What an undoable step changed, to go back and forth without keeping
+/// copies of the game.
+enum Step {
+    Play {
+        /// The ops of the other side of the step from `ops_from` on. A step
+        /// changes at most the last op and appends to it.
+        ops_from: usize,
+        ops: Vec<GameOP>,
+        brick: Brick,
+        brick_pos: Vec2,
+        /// The board, which ending the game can change without locking.
+        grids: GameGrids,
+        game_over: bool,
+        /// The brick the step locked, made again by a redo and unmade by an
+        /// undo.
+        locked: Option<(u16, StateUndo)>,
+    },
+    /// A loaded game replaced everything.
+    Load(Snapshot),
+}
+
+/// The whole game, to undo a load.
+struct Snapshot {
+    state: GameState,
+    brick_pos: Vec2,
+    brick: Brick,
+    ops: Vec<GameOP>,
+    game_over: bool,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Self {
-        Self {
-            state: GameState::initial_state(),
-            brick: Brick::from_random_num(0, 0),
+        let state = GameState::initial_state();
+        let mut game = Self {
+            brick: Self::brick_at(&state, 0),
+            state,
             brick_pos: game::INITIAL_POS,
-            renderer: GameRenderer::new(),
+            ops: Vec::new(),
+            message: String::new(),
+            game_over: false,
+            undo_history: Vec::new(),
+            redo_history: Vec::new(),
+            last_lock: None,
+            hint: None,
+        };
+        op::track_op(&mut game.ops, GameOP::New);
+        game
+    }
+
+    /// Rebuild a game from a recorded op sequence, which starts with the `N`
+    /// that spawns the first brick.
+    pub fn from_ops(ops: &[GameOP]) -> io::Result<Self> {
+        let mut game = Self::new();
+        match ops.split_first() {
+            Some((GameOP::New, ops)) => ops.iter().for_each(|op| game.update(*op)),
+            Some(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "op sequence must start with N"))
+            }
+            None => (),
         }
+        Ok(game)
+    }
+
+    /// Load a game saved by `save`.
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::from_ops(&op::parse_op_sequence(&fs::read_to_string(path)?)?)
     }
 
-    pub fn start(mut self) {
+    /// Write the ops applied so far as an op-sequence file.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.ops.to_op_string())
+    }
+
+    /// Play interactively, `path` is the file the game is saved to and loaded from.
+    pub fn start(mut self, path: &str, renderer: &mut impl RenderGame) {
         let mut input = game_io::GameInput::new();
-        self.render();
-        
+
         loop {
-            let op = input.get_input();
-            if !self.update(op) {
-                
-                self.render();
-                println!("Game Over!");
-                return;
+            self.render(renderer);
+            match input.get_input() {
+                PlayInput::Op(op) => self.with_undo(|game| game.update(op)),
+                PlayInput::Drop => self.with_undo(Self::hard_drop),
+                PlayInput::Undo => {
+                    if !self.undo() {
+                        self.message = "Nothing to undo".to_string();
+                    }
+                }
+                PlayInput::Redo => {
+                    if !self.redo() {
+                        self.message = "Nothing to redo".to_string();
+                    }
+                }
+                PlayInput::Save => {
+                    self.message = match self.save(path) {
+                        Ok(()) => format!("Saved {} ops to {}", self.ops.len(), path),
+                        Err(err) => format!("Failed to save {}: {}", path, err),
+                    }
+                }
+                PlayInput::Load => match Self::load(path) {
+                    Ok(loaded) => {
+                        let step = Step::Load(self.snapshot());
+                        self.push_step(step);
+                        self.restore(loaded.snapshot());
+                        self.message = format!("Loaded {} bricks from {}", self.state.brick_count, path);
+                    }
+                    Err(err) => self.message = format!("Failed to load {}: {}", path, err),
+                },
+                PlayInput::Hint => {
+                    self.message = match self.hint() {
+                        Some(hint) => hint.describe(),
+                        None => "No placement found".to_string(),
+                    }
+                }
+                PlayInput::Quit => return,
             }
-            
-            self.render();
-        }
-    }
-
-    pub fn update(&mut self, op: GameOP) -> bool
-    {
-        
-        let next_pos = match op {
-            GameOP::New => {
-                game::INITIAL_POS
-            },
-            GameOP::Rotate(_) => {
-                self.brick_pos
-            },
-            GameOP::Left(dx) => self.brick_pos + Vec2(-dx, 0),
-            GameOP::Right(dx) => self.brick_pos + Vec2(dx, 0),
-            GameOP::Down(dy) => self.brick_pos + Vec2(0, dy),
-        };
+        }
+    }
 
-        if self.state.grids.brick_pos_valid(&self.brick, next_pos, true) {
-            match op {
-                GameOP::New => {
-                    self.state.grids.place_teris_brick(&self.brick, self.brick_pos);
-                    self.state.evaluate_score();
-                    let next_brick = self.state.next_brick();
-                    if !self.state.grids.brick_pos_valid(&next_brick, game::INITIAL_POS, true) {
-                        return false;
+    /// Search for the best placement of the current brick, it is shown until
+    /// the game changes.
+    pub fn hint(&mut self) -> Option<&Hint> {
+        if self.game_over {
+            return None;
+        }
+        let child = TetrisAuto::suggest(&self.state, HINT_DEPTH, HINT_WIDTH)?;
+        let (pos, rot) = game::decode_placement(*child.brick_stack.last()?);
+        let brick = Self::brick_at(&self.state, 0).rotate_n(rot);
+        self.hint = Some(Hint {
+            brick,
+            pos,
+            ops: self.path_to(brick, pos),
+            sp_score: child.sp_score,
+            intended_sp_score: self.intended_sp_score(),
+        });
+        self.hint.as_ref()
+    }
+
+    fn intended_sp_score(&self) -> Option<i32> {
+        let pos = self.ghost_pos();
+        if self.brick.pos_with_center(pos).iter().any(|pos| pos.1 < 0) {
+            return None;
+        }
+        let mut state = self.state.clone();
+        state.place_brick(&self.brick, pos, self.rotations());
+        Some(state.sp_score)
+    }
+
+    /// Ops moving the current brick to `target` at `target_pos`: rotate and
+    /// move sideways in either order, then drop, checking every single step.
+    fn path_to(&self, target: Brick, target_pos: Vec2) -> Option<Vec<GameOP>> {
+        let grids = &self.state.grids;
+        let rotations = ((target.1 + 4 - self.brick.1) % 4) as i8;
+        let offset = target_pos - self.brick_pos;
+        if offset.1 < 0 {
+            return None;
+        }
+        let horizontal = match offset.0 {
+            dx if dx < 0 => GameOP::Left(-dx),
+            dx => GameOP::Right(dx),
+        };
+        let orders = [
+            [GameOP::Rotate(rotations), horizontal, GameOP::Down(offset.1)],
+            [horizontal, GameOP::Rotate(rotations), GameOP::Down(offset.1)],
+        ];
+        orders.iter().find_map(|order| {
+            let mut brick = self.brick;
+            let mut pos = self.brick_pos;
+            let mut ops = Vec::new();
+            for op in order {
+                let (count, step) = match *op {
+                    GameOP::Left(count) => (count, Some(Vec2(-1, 0))),
+                    GameOP::Right(count) => (count, Some(Vec2(1, 0))),
+                    GameOP::Down(count) => (count, Some(Vec2(0, 1))),
+                    GameOP::Rotate(count) => (count, None),
+                    GameOP::New => (0, None),
+                };
+                for _ in 0..count {
+                    match step {
+                        Some(step) => pos = pos + step,
+                        None => brick = brick.rotate(),
                     }
-                    self.brick = next_brick;
-                },
-                GameOP::Rotate(rot) => {
-                    let mut brick = self.brick;
-                    for _ in 0..rot {
-                        brick = brick.rotate();
+                    if !grids.brick_pos_valid(&brick, pos, true) {
+                        return None;
                     }
-                    if !self.state.grids.brick_pos_valid(&brick, self.brick_pos, true) {
-                        return true;
+                }
+                if count > 0 {
+                    ops.push(*op);
+                }
+            }
+            Some(ops)
+        })
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn ops(&self) -> &[GameOP] {
+        &self.ops
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// The falling brick and its position, `None` once the game is over.
+    pub fn current_brick(&self) -> Option<(Brick, Vec2)> {
+        (!self.game_over).then_some((self.brick, self.brick_pos))
+    }
+
+    /// Apply `change` as one step that `undo` can revert. Changes that
+    /// neither apply an op nor end the game are not recorded.
+    pub fn with_undo(&mut self, change: impl FnOnce(&mut Self)) {
+        let ops_from = self.ops.len().saturating_sub(1);
+        let ops = self.ops[ops_from..].to_vec();
+        let brick_count = self.state.brick_count;
+        let grids = self.state.grids.clone();
+        let (brick, brick_pos, game_over) = (self.brick, self.brick_pos, self.game_over);
+        self.last_lock = None;
+        change(self);
+        let changed = self.ops.len() != ops_from + ops.len()
+            || self.ops[ops_from..] != ops[..]
+            || self.state.brick_count != brick_count
+            || self.game_over != game_over;
+        if changed {
+            let locked = self.last_lock.take();
+            self.push_step(Step::Play { ops_from, ops, brick, brick_pos, grids, game_over, locked });
+        }
+    }
+
+    fn push_step(&mut self, step: Step) {
+        self.undo_history.push(step);
+        self.redo_history.clear();
+        self.hint = None;
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.undo_history.pop() {
+            Some(step) => {
+                let redo = self.take_step(step, false);
+                self.redo_history.push(redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.redo_history.pop() {
+            Some(step) => {
+                let undo = self.take_step(step, true);
+                self.undo_history.push(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Go to the other side of `step`, locking its brick again if `forward`
+    /// and unlocking it otherwise. Returns the step that comes back.
+    fn take_step(&mut self, step: Step, forward: bool) -> Step {
+        let back = match step {
+            Step::Play { ops_from, ops, brick, brick_pos, grids, game_over, locked } => {
+                let other_ops = self.ops.split_off(ops_from);
+                self.ops.extend(ops);
+                let other_grids = self.state.grids.clone();
+                let locked = locked.map(|(placement, undo)| match forward {
+                    true => (placement, self.state.make(placement)),
+                    false => {
+                        self.state.unmake(&undo);
+                        (placement, undo)
                     }
-                    self.brick = brick;
+                });
+                self.state.grids = grids;
+                Step::Play {
+                    ops_from,
+                    ops: other_ops,
+                    brick: mem::replace(&mut self.brick, brick),
+                    brick_pos: mem::replace(&mut self.brick_pos, brick_pos),
+                    grids: other_grids,
+                    game_over: mem::replace(&mut self.game_over, game_over),
+                    locked,
                 }
-                _ =>(),
             }
+            Step::Load(snapshot) => {
+                let back = Step::Load(self.snapshot());
+                self.restore(snapshot);
+                back
+            }
+        };
+        self.message.clear();
+        self.hint = None;
+        back
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.state.clone(),
+            brick_pos: self.brick_pos,
+            brick: self.brick,
+            ops: self.ops.clone(),
+            game_over: self.game_over,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.state = snapshot.state;
+        self.brick_pos = snapshot.brick_pos;
+        self.brick = snapshot.brick;
+        self.ops = snapshot.ops;
+        self.game_over = snapshot.game_over;
+        self.message.clear();
+        self.hint = None;
+    }
+
+    /// Apply `op` like the web game: a move or rotation that ends in an invalid
+    /// position is ignored, `New` locks the brick where it is.
+    pub fn update(&mut self, op: GameOP) {
+        if self.game_over {
+            return;
+        }
+        match op {
+            GameOP::New => self.lock_brick(),
+            GameOP::Rotate(rot) => {
+                for _ in 0..rot {
+                    let brick = self.brick.rotate();
+                    if self.state.grids.brick_pos_valid(&brick, self.brick_pos, true) {
+                        self.brick = brick;
+                        op::track_op(&mut self.ops, GameOP::Rotate(1));
+                    }
+                }
+            }
+            GameOP::Left(dx) => self.move_brick(Vec2(-dx, 0), op),
+            GameOP::Right(dx) => self.move_brick(Vec2(dx, 0), op),
+            GameOP::Down(dy) => self.move_brick(Vec2(0, dy), op),
+        }
+    }
+
+    /// Drop the brick to the bottom and lock it, as `Tetris.drop` followed by a new brick.
+    pub fn hard_drop(&mut self) {
+        let distance = self.ghost_pos().1 - self.brick_pos.1;
+        if distance > 0 {
+            self.update(GameOP::Down(distance));
+        }
+        self.update(GameOP::New);
+    }
+
+    /// Where the current brick lands on a hard drop.
+    pub fn ghost_pos(&self) -> Vec2 {
+        let mut pos = self.brick_pos;
+        while self.state.grids.brick_pos_valid(&self.brick, pos + Vec2(0, 1), true) {
+            pos.1 += 1;
+        }
+        pos
+    }
+
+    pub fn render(&self, renderer: &mut impl RenderGame) {
+        renderer.render_game(&self.state);
+        renderer.render_user_hint();
+        if !self.game_over {
+            renderer.render_next_brick(&Self::brick_at(&self.state, 1));
+            renderer.render_ghost(&self.brick, self.ghost_pos());
+            if let Some(hint) = &self.hint {
+                renderer.render_hint(&hint.brick, hint.pos);
+            }
+            renderer.render_brick(&self.brick, self.brick_pos);
+        }
+        renderer.render_message(&self.message);
+        renderer.flush();
+    }
+
+    /// The brick spawned `ahead` bricks after the one `state` places next.
+    fn brick_at(state: &GameState, ahead: usize) -> Brick {
+        let mut rand_num = state.rand_num;
+        for _ in 0..=ahead {
+            rand_num = get_random_num(rand_num);
+        }
+        Brick::from_random_num(rand_num, state.brick_count + ahead)
+    }
+
+    /// Rotations applied to the current brick since it spawned.
+    fn rotations(&self) -> usize {
+        (self.brick.1 + 4 - self.state.brick_count % 4) % 4
+    }
+
+    fn move_brick(&mut self, offset: Vec2, op: GameOP) {
+        let next_pos = self.brick_pos + offset;
+        if self.state.grids.brick_pos_valid(&self.brick, next_pos, true) {
             self.brick_pos = next_pos;
+            op::track_op(&mut self.ops, op);
         }
+    }
 
-        true
+    fn lock_brick(&mut self) {
+        let mut grids = self.state.grids.clone();
+        // Cells above the board would be lost, the game is over at that point anyway.
+        if self.brick.pos_with_center(self.brick_pos).iter().any(|pos| pos.1 < 0) {
+            self.end_game("Game Over! The stack reached the top.");
+            return;
+        }
+        grids.place_teris_brick(&self.brick, self.brick_pos);
+        // game.core.js ends the game without scoring once every row is occupied.
+        if (0..GRID_HEIGHT as i8).all(|row| grids.get_row(row) != 0) {
+            self.state.grids = grids;
+            self.end_game("Game Over! The stack reached the top.");
+            return;
+        }
+        let cleared = (0..GRID_HEIGHT as i8).filter(|row| grids.is_full_row(*row)).count();
+
+        let score = self.state.score;
+        let placement = game::encode_placement(self.brick_pos, self.rotations());
+        self.last_lock = Some((placement, self.state.make(placement)));
+        self.message = match cleared {
+            0 => String::new(),
+            _ => format!("Cleared {} row(s): +{}", cleared, self.state.score - score),
+        };
+        if self.state.brick_count >= MAX_BRICKS_COUNT {
+            self.end_game("Game Over! All bricks placed.");
+            return;
+        }
+
+        self.brick = Self::brick_at(&self.state, 0);
+        self.brick_pos = game::INITIAL_POS;
+        op::track_op(&mut self.ops, GameOP::New);
+        if !self.state.grids.brick_pos_valid(&self.brick, self.brick_pos, true) {
+            self.end_game("Game Over! No room for the next brick.");
+        }
     }
 
+    fn end_game(&mut self, message: &str) {
+        self.game_over = true;
+        self.message = format!("{} Score: {}", message, self.state.score);
+    }
+}
 
-    pub fn render(&mut self) {
-        self.renderer.render_game(&self.state);
-        self.renderer.render_user_hint();
-        self.renderer.render_brick(&self.brick, self.brick_pos);
+impl Hint {
+    pub fn describe(&self) -> String {
+        let ops = match &self.ops {
+            Some(ops) if ops.is_empty() => "N".to_string(),
+            Some(ops) => ops.to_op_string(),
+            None => "unreachable from here".to_string(),
+        };
+        match self.intended_sp_score {
+            Some(intended) => format!(
+                "Hint: {} sp_score {} ({:+} vs. drop here)",
+                ops,
+                self.sp_score,
+                self.sp_score - intended
+            ),
+            None => format!("Hint: {} sp_score {}", ops, self.sp_score),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        auto::{TetrisAuto, HINT_DEPTH, HINT_WIDTH},
+        game::{push_placement_ops, GameState, MAX_BRICKS_COUNT, MAX_PLACEMENTS},
+        op::{GameOP, GameOPStr},
+        render::{TextFrames, TextRenderer},
+    };
+
+    use super::Game;
+
+    #[test]
+    fn test_ops_are_coalesced() {
+        let mut game = Game::new();
+        for op in [GameOP::Left(1), GameOP::Left(1), GameOP::Left(1), GameOP::Rotate(1)] {
+            game.update(op);
+        }
+        game.hard_drop();
+        let ops = game.ops().to_op_string();
+        assert!(ops.starts_with("N,L3,C1,D"), "{}", ops);
+        assert!(ops.ends_with(",N"), "{}", ops);
+    }
+
+    #[test]
+    fn test_hard_drop_matches_replay() {
+        let mut game = Game::new();
+        for op in [GameOP::Left(3), GameOP::Rotate(1), GameOP::Right(3)] {
+            game.update(op);
+            game.hard_drop();
+        }
+        assert_eq!(game.state().brick_count, 3);
+        let replayed = GameState::from_brick_stack(&game.state().brick_stack);
+        assert_eq!(replayed.grids, game.state().grids);
+        assert_eq!(replayed.get_op_sequence(), game.ops()[..game.ops().len() - 1]);
     }
 
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::new();
+        game.with_undo(|game| game.update(GameOP::Left(1)));
+        game.with_undo(Game::hard_drop);
+        game.with_undo(|game| game.update(GameOP::Right(20)));
+        let dropped = game.ops().to_vec();
+        assert_eq!(game.state().brick_count, 1);
+
+        assert!(game.undo());
+        assert_eq!(game.state().brick_count, 0);
+        assert_eq!(game.ops(), [GameOP::New, GameOP::Left(1)]);
+        assert!(game.undo());
+        assert!(!game.undo());
+        assert_eq!(game.ops(), [GameOP::New]);
+
+        assert!(game.redo());
+        assert!(game.redo());
+        assert!(!game.redo());
+        assert_eq!(game.ops(), dropped);
+        assert_eq!(game.state().brick_count, 1);
+
+        game.undo();
+        game.with_undo(|game| game.update(GameOP::Rotate(1)));
+        assert!(!game.redo());
+    }
+
+    #[test]
+    fn test_undo_redo_whole_game() {
+        let mut game = Game::new();
+        let view = |game: &Game| {
+            let state = game.state();
+            let brick = game.current_brick().map(|(brick, pos)| (brick.0, brick.1, pos));
+            (state.grids.clone(), state.score, state.sp_score, state.brick_stack.clone(), game.ops().to_vec(), brick)
+        };
+        let mut views = vec![view(&game)];
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        // Clear some rows playing the best placements, then stack up to the top.
+        for brick in 0.. {
+            let mut path = vec![GameOP::New];
+            if brick < 40 {
+                let len = game.state().next(&mut next_states);
+                let best = next_states[..len].iter().max_by_key(|state| state.sp_score).unwrap();
+                path.clear();
+                push_placement_ops(&mut path, *best.brick_stack.last().unwrap());
+            }
+            for op in &path[1..] {
+                game.with_undo(|game| game.update(*op));
+                views.push(view(&game));
+            }
+            game.with_undo(Game::hard_drop);
+            if view(&game) != views[views.len() - 1] {
+                views.push(view(&game));
+            }
+            if game.is_over() {
+                break;
+            }
+        }
+        assert!(game.state().score > 0);
+        for expected in views.iter().rev().skip(1) {
+            assert!(game.undo());
+            assert_eq!(view(&game), *expected);
+        }
+        assert!(!game.undo());
+        for expected in &views[1..] {
+            assert!(game.redo());
+            assert_eq!(view(&game), *expected);
+        }
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut game = Game::new();
+        for op in [GameOP::Left(2), GameOP::Rotate(1)] {
+            game.update(op);
+            game.hard_drop();
+        }
+        game.update(GameOP::Right(1));
+        let path = std::env::temp_dir().join(format!("tetris_play_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        game.save(path).unwrap();
+        let loaded = Game::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.ops(), game.ops());
+        assert_eq!(loaded.state().grids, game.state().grids);
+        assert_eq!(loaded.state().score, game.state().score);
+        assert_eq!(loaded.ghost_pos(), game.ghost_pos());
+        assert!(Game::from_ops(&[GameOP::Left(1)]).is_err());
+    }
+
+    #[test]
+    fn test_hint() {
+        let mut game = Game::new();
+        game.update(GameOP::Left(2));
+        game.hard_drop();
+        let hint = game.hint().unwrap();
+        let (pos, ops) = (hint.pos, hint.ops.clone().unwrap());
+        assert!(hint.intended_sp_score.is_some());
+
+        let mut renderer = TextRenderer::new(TextFrames::in_memory());
+        game.render(&mut renderer);
+        let frame = renderer.sink().frames()[0].lines().map(|line| line.chars().collect::<Vec<_>>()).collect::<Vec<_>>();
+        let active = game.brick.pos_with_center(game.brick_pos);
+        for cell in game.hint.as_ref().unwrap().brick.pos_with_center(pos) {
+            let expected = if active.contains(&cell) { '*' } else { '+' };
+            assert_eq!(frame[cell.1 as usize][cell.0 as usize], expected);
+        }
+
+        for op in ops {
+            game.with_undo(|game| game.update(op));
+        }
+        assert!(game.hint.is_none());
+        assert_eq!(game.ghost_pos(), pos);
+        game.hard_drop();
+        let suggested = TetrisAuto::suggest(&GameState::from_brick_stack(&game.state().brick_stack[..1]), HINT_DEPTH, HINT_WIDTH).unwrap();
+        assert_eq!(game.state().brick_stack, suggested.brick_stack);
+    }
+
+    #[test]
+    fn test_game_over() {
+        let mut game = Game::new();
+        while !game.is_over() {
+            game.hard_drop();
+        }
+        assert!(game.state().brick_count < MAX_BRICKS_COUNT);
+        let brick_count = game.state().brick_count;
+        game.hard_drop();
+        assert_eq!(game.state().brick_count, brick_count);
+    }
 }
@@ -3,6 +3,8 @@ use crate::{brick::Brick, vec2::Vec2};
 
 pub const GRID_WIDTH: u32 = 10;
 pub const GRID_HEIGHT: u32 = 20;
+/// Four rows of the floor below the board, in the layout of one word.
+const FLOOR: u64 = 0x03FF_03FF_03FF_03FF;
 
 /*
 
@@ -14,11 +16,28 @@ pub const GRID_HEIGHT: u32 = 20;
 
 */
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
 pub struct GameGrids {
     bits: [u64; 5],
 }
 
+/// What `GameGrids::make` and `GameGrids::clear_full_rows` changed, so
+/// `GameGrids::unmake` can restore the board without keeping a copy.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct GridUndo {
+    /// The cells of the placed brick in the layout of `GameGrids`.
+    placed: [u64; 5],
+    /// The cleared rows and their bits, in the order they were removed.
+    cleared: [(i8, u64); 4],
+    cleared_count: usize,
+}
+
+impl GridUndo {
+    pub fn cleared(&self) -> &[(i8, u64)] {
+        &self.cleared[..self.cleared_count]
+    }
+}
+
 impl GameGrids {
     pub fn new()-> Self {
         Self {
@@ -26,6 +45,52 @@ impl GameGrids {
         }
     }
 
+    pub fn from_bits(bits: [u64; 5]) -> Self {
+        Self { bits }
+    }
+
+    pub fn bits(&self) -> [u64; 5] {
+        self.bits
+    }
+
+    /// Parse a board written by `to_ascii`: `GRID_HEIGHT` rows from the top
+    /// down, each `GRID_WIDTH` cells of `#` (occupied) or `.` (empty).
+    /// Whitespace around the rows and blank lines are ignored. Rows are
+    /// numbered from 0 at the top in the errors.
+    pub fn from_ascii(text: &str) -> Result<Self, String> {
+        let rows = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>();
+        if rows.len() != GRID_HEIGHT as usize {
+            return Err(format!("expected {} rows, found {}", GRID_HEIGHT, rows.len()));
+        }
+        let mut grids = Self::new();
+        for (y, row) in rows.iter().enumerate() {
+            let cells = row.chars().count();
+            if cells != GRID_WIDTH as usize {
+                return Err(format!("row {} has {} cells, expected {}: {}", y, cells, GRID_WIDTH, row));
+            }
+            for (x, cell) in row.chars().enumerate() {
+                match cell {
+                    '#' => grids.set_block(Vec2(x as i8, y as i8)),
+                    '.' => (),
+                    _ => return Err(format!("invalid cell {:?} in row {}, expected '#' or '.'", cell, y)),
+                }
+            }
+        }
+        Ok(grids)
+    }
+
+    /// The board as `GRID_HEIGHT` lines of `#` and `.`, see `from_ascii`.
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::with_capacity(((GRID_WIDTH + 1) * GRID_HEIGHT) as usize);
+        for y in 0..GRID_HEIGHT as i8 {
+            for x in 0..GRID_WIDTH as i8 {
+                text.push(if self.get(Vec2(x, y)) { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
     #[inline(always)]
     fn pos_to_nint(y: i8) -> usize {
         y as usize / 4
@@ -48,7 +113,7 @@ impl GameGrids {
 
     #[inline(always)]
     fn row_mask(y: i8) -> (usize, u64) {
-        (Self::pos_to_nint(y), 0b1111_1111_11 << (Self::pos_to_nseg(y) * 16))
+        (Self::pos_to_nint(y), 0b11_1111_1111 << (Self::pos_to_nseg(y) * 16))
     }
 
     #[inline(always)]
@@ -60,7 +125,7 @@ impl GameGrids {
     #[inline(always)]
     pub fn get(&self, pos: Vec2) -> bool {
         let (nint, nbit) = Self::pos_to_idx(pos);
-        (self.bits[nint as usize] & (1 << nbit)) != 0
+        (self.bits[nint] & (1 << nbit)) != 0
     }
 
     #[inline(always)]
@@ -71,7 +136,7 @@ impl GameGrids {
     #[inline(always)]
     pub fn set(&mut self, pos: Vec2, val: u64)  {
         let (nint, nbit) = Self::pos_to_idx(pos);
-        self.bits[nint as usize] |= val << nbit;
+        self.bits[nint] |= val << nbit;
     }
 
     #[inline(always)]
@@ -91,46 +156,44 @@ impl GameGrids {
 
     #[inline(always)]
     pub fn place_teris_brick(&mut self, brick: &Brick, center: Vec2) {
-        let brick_pos = brick.get_pos();
-        for i in 0..4 {
-            self.set_block(brick_pos[i] + center);
+        for pos in brick.get_pos() {
+            self.set_block(*pos + center);
         }
     }
     
+    /// Rows `top..top + 4` shifted into one word, `top` in the lowest 16
+    /// bits. Rows above the board are empty and rows below it full, so a
+    /// `Brick::row_mask` collides with the floor but not with the sky.
     #[inline(always)]
-    pub fn can_place_brick(&self, brick: &Brick, center: Vec2) -> bool {
-        if !self.brick_pos_valid(brick, center, false) {
-            return false;
-        }
-        let lower_bound = brick.get_lower_bound();
-        let mut can_place = false;
-        for bound_pos in lower_bound {
-            let pos = center + *bound_pos;
-            
-            can_place = can_place || pos.1 == 20 || self.get(pos);
+    pub fn rows_window(&self, top: i8) -> u64 {
+        match top {
+            i8::MIN..=-4 => 0,
+            20.. => FLOOR,
+            _ => {
+                let padded = [0, self.bits[0], self.bits[1], self.bits[2], self.bits[3], self.bits[4], FLOOR];
+                let (nint, nseg) = ((top + 4) as usize / 4, (top + 4) as usize % 4);
+                match nseg {
+                    0 => padded[nint],
+                    _ => (padded[nint] >> (nseg * 16)) | (padded[nint + 1] << (64 - nseg * 16)),
+                }
+            }
         }
-        can_place
     }
 
+    /// Whether the brick at `center` rests on the stack or the floor.
     #[inline(always)]
-    pub fn brick_pos_valid(&self, brick: &Brick, center: Vec2, allow_outbound: bool) -> bool {
-        let brick_pos = brick.get_pos();
-        for i in 0..4 {
-            let pos = brick_pos[i] + center;
-            match pos {
-                Vec2(0..=9, 0..=19) => (),
-                Vec2(0..=9, y) if y < 20 => match allow_outbound {
-                    true => continue,
-                    false => return false,
-                },
-                _ => return false,
-            }
-            if self.get(brick_pos[i] + center) {
-                return false;
-            }
-        }
+    pub fn can_place_brick(&self, brick: &Brick, center: Vec2) -> bool {
+        self.brick_pos_valid(brick, center, false)
+            && self.rows_window(brick.get_top_pos(center.1) + 1) & brick.row_mask(center.0) != 0
+    }
 
-        true
+    /// Whether the brick at `center` is inside the board and clear of the
+    /// stack. With `allow_outbound` its cells may stick out above the board.
+    #[inline(always)]
+    pub fn brick_pos_valid(&self, brick: &Brick, center: Vec2, allow_outbound: bool) -> bool {
+        let mask = brick.row_mask(center.0);
+        let top = brick.get_top_pos(center.1);
+        mask != 0 && (allow_outbound || top >= 0) && self.rows_window(top) & mask == 0
     }
 
     // #[inline(always)]
@@ -146,6 +209,28 @@ impl GameGrids {
         (row + 1) & (1 << 10) != 0
     }
 
+    /// Height of the stack in each column.
+    pub fn column_heights(&self) -> [u8; GRID_WIDTH as usize] {
+        let mut heights = [0; GRID_WIDTH as usize];
+        let mut seen = 0;
+        for y in 0..GRID_HEIGHT as i8 {
+            let mut top = self.get_row(y) & !seen;
+            seen |= top;
+            while top != 0 {
+                heights[top.trailing_zeros() as usize] = (GRID_HEIGHT as i8 - y) as u8;
+                top &= top - 1;
+            }
+        }
+        heights
+    }
+
+    /// Number of rows from the bottom up to the highest occupied cell.
+    pub fn stack_height(&self) -> usize {
+        (0..GRID_HEIGHT as i8)
+            .find(|row| self.get_row(*row) != 0)
+            .map_or(0, |row| GRID_HEIGHT as usize - row as usize)
+    }
+
     pub fn blocks_in_row(&self, y: i8) -> usize {
         let row = self.get_row(y);
         10 - zeros_in_num(row, 10)
@@ -169,6 +254,96 @@ impl GameGrids {
         self.bits[0] &= 0xFFFF_FFFF_FFFF_0000;
         rowbits
     }
+
+    /// Undo `remove_row(y)`: the rows down to `y` move up by one, the top row
+    /// is dropped, and row `y` becomes `row`.
+    #[inline]
+    pub fn insert_row(&mut self, y: i8, row: u64) {
+        let nint = Self::pos_to_nint(y);
+        let nseg = Self::pos_to_nseg(y);
+        for nint in 0..nint {
+            self.bits[nint] = (self.bits[nint] >> 16) | ((self.bits[nint + 1] & 0xFFFF) << 48);
+        }
+        let lower_mask = (1 << (nseg * 16)) - 1;
+        let higher_mask = match nseg {
+            3 => 0,
+            _ => u64::MAX << ((nseg as u64 + 1) * 16)
+        };
+        self.bits[nint] = (self.bits[nint] & higher_mask)
+            | ((self.bits[nint] >> 16) & lower_mask)
+            | (row << (nseg * 16));
+    }
+
+    /// Place `brick` at `center` like `place_teris_brick`, returning the
+    /// record `unmake` takes it back with.
+    pub fn make(&mut self, brick: &Brick, center: Vec2) -> GridUndo {
+        let mut undo = GridUndo::default();
+        for pos in brick.pos_with_center(center) {
+            let (nint, nbit) = Self::pos_to_idx(pos);
+            undo.placed[nint] |= 1 << nbit;
+        }
+        for (bits, placed) in self.bits.iter_mut().zip(undo.placed) {
+            *bits |= placed;
+        }
+        undo
+    }
+
+    /// `remove_row` recording the row in `undo`, which must have room left.
+    #[inline]
+    fn clear_row(&mut self, y: i8, undo: &mut GridUndo) {
+        undo.cleared[undo.cleared_count] = (y, self.remove_row(y));
+        undo.cleared_count += 1;
+    }
+
+    /// Remove the full rows from the top down, recording them in `undo`.
+    /// Returns how many were removed.
+    ///
+    /// A brick fills at most four rows of a board that had no full ones, so
+    /// `undo` has room for four. With more full rows, or fewer free slots in
+    /// `undo`, nothing is removed and an error returned.
+    pub fn clear_full_rows(&mut self, undo: &mut GridUndo) -> Result<usize, String> {
+        let full = self.full_rows();
+        if undo.cleared_count + full > undo.cleared.len() {
+            return Err(format!(
+                "{} full rows, at most {} can be recorded",
+                full,
+                undo.cleared.len() - undo.cleared_count
+            ));
+        }
+        for row in 0..GRID_HEIGHT as i8 {
+            if self.is_full_row(row) {
+                self.clear_row(row, undo);
+            }
+        }
+        Ok(full)
+    }
+
+    /// Remove the full rows without recording them, returns how many there were.
+    pub fn remove_full_rows(&mut self) -> usize {
+        let mut count = 0;
+        for row in 0..GRID_HEIGHT as i8 {
+            if self.is_full_row(row) {
+                self.remove_row(row);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn full_rows(&self) -> usize {
+        (0..GRID_HEIGHT as i8).filter(|row| self.is_full_row(*row)).count()
+    }
+
+    /// Restore the board as it was before the `make` and `clear_full_rows`
+    /// that recorded `undo`.
+    pub fn unmake(&mut self, undo: &GridUndo) {
+        for (y, row) in undo.cleared().iter().rev() {
+            self.insert_row(*y, *row);
+        }
+        for (bits, placed) in self.bits.iter_mut().zip(undo.placed) {
+            *bits &= !placed;
+        }
+    }
 }
 
 fn zeros_in_num(mut x: u64, max_pos: usize) -> usize {
@@ -187,9 +362,82 @@ fn zeros_in_num(mut x: u64, max_pos: usize) -> usize {
 
 #[cfg(test)]
 mod test {
-    use crate::grid::zeros_in_num;
+    use proptest::prelude::*;
+
+    use crate::{brick::Brick, grid::{zeros_in_num, GameGrids, GridUndo}, vec2::Vec2};
 
     #[test]
+    fn test_ascii() {
+        let text = "..........\n".repeat(17) + "....#.....\n##.######.\n#########.\n";
+        let grids = GameGrids::from_ascii(&text).unwrap();
+        assert!(grids.get(Vec2(4, 17)) && grids.get(Vec2(0, 19)) && !grids.get(Vec2(9, 19)));
+        assert_eq!(grids.blocks_in_row(18), 8);
+        assert_eq!(grids.to_ascii(), text);
+
+        let short = "..........\n".repeat(19);
+        assert_eq!(GameGrids::from_ascii(&short).unwrap_err(), "expected 20 rows, found 19");
+        let narrow = "..........\n".repeat(19) + "#########\n";
+        assert_eq!(
+            GameGrids::from_ascii(&narrow).unwrap_err(),
+            "row 19 has 9 cells, expected 10: #########"
+        );
+        let invalid = "..........\n".repeat(19) + "####x#####\n";
+        assert_eq!(
+            GameGrids::from_ascii(&invalid).unwrap_err(),
+            "invalid cell 'x' in row 19, expected '#' or '.'"
+        );
+    }
+
+    /// A board from a picture of its lowest rows, the rows above are empty.
+    fn board(picture: &str) -> GameGrids {
+        let rows = picture.lines().filter(|line| !line.trim().is_empty()).count();
+        GameGrids::from_ascii(&("..........\n".repeat(20 - rows) + picture)).unwrap()
+    }
+
+    #[test]
+    fn test_remove_row() {
+        let mut grids = board("
+            #.........
+            .#........
+            ##########
+            ...#......
+            ....#....#
+        ");
+        assert_eq!(grids.remove_row(17), 0x3FF);
+        assert_eq!(grids, board("
+            #.........
+            .#........
+            ...#......
+            ....#....#
+        "));
+        assert_eq!(grids.remove_row(19), 0x210);
+        assert_eq!(grids, board("
+            #.........
+            .#........
+            ...#......
+        "));
+    }
+
+    #[test]
+    fn test_too_many_full_rows() {
+        let picture = "
+            ##########
+            ##########
+            #.........
+            ##########
+            ##########
+            ##########
+        ";
+        let mut grids = board(picture);
+        let mut undo = GridUndo::default();
+        assert_eq!(grids.clear_full_rows(&mut undo), Err("5 full rows, at most 4 can be recorded".to_string()));
+        assert_eq!((&grids, undo), (&board(picture), GridUndo::default()));
+        assert_eq!(grids.remove_full_rows(), 5);
+        assert_eq!(grids, board("#........."));
+    }
+
+    #[test]
+    #[allow(clippy::unusual_byte_groupings)]
     fn test_zeros_in_num() {
         assert_eq!(zeros_in_num(0b11_1111_1111, 10), 0);
         assert_eq!(zeros_in_num(0b11_1111_1110, 10), 1);
@@ -200,4 +448,77 @@ mod test {
         assert_eq!(zeros_in_num(0b11100_1111_1111, 10), 2);
         assert_eq!(zeros_in_num(0b00_1111_1111, 10), 2);
     }
-}
\ No newline at end of file
+
+    proptest! {
+        #[test]
+        fn brick_masks_match_cells(
+            bits in prop::array::uniform5(any::<u64>()),
+            shape in 0..7usize,
+            state in 0..4usize,
+            x in -3..13i8,
+            y in -5..23i8,
+            allow_outbound in any::<bool>(),
+        ) {
+            let grids = GameGrids::from_bits(bits.map(|bits| bits & 0x03FF_03FF_03FF_03FF));
+            let brick = Brick(shape, state);
+            let fits = |center: Vec2, allow_outbound: bool| {
+                brick.get_pos().iter().all(|offset| match *offset + center {
+                    pos @ Vec2(0..=9, 0..=19) => grids.is_empty(pos),
+                    Vec2(0..=9, y) => allow_outbound && y < 0,
+                    _ => false,
+                })
+            };
+            let center = Vec2(x, y);
+            prop_assert_eq!(grids.brick_pos_valid(&brick, center, allow_outbound), fits(center, allow_outbound));
+            prop_assert_eq!(
+                grids.can_place_brick(&brick, center),
+                fits(center, false) && !fits(center + Vec2(0, 1), true)
+            );
+        }
+
+        #[test]
+        fn unmake_restores_the_board(
+            rows in prop::array::uniform20(0..0x3FFu64),
+            holes in prop::array::uniform20(0..10u32),
+            stack_top in 4..20usize,
+            shape in 0..7usize,
+            state in 0..4usize,
+            x in 0..10i8,
+        ) {
+            // Dense rows with one hole each, so bricks often clear some.
+            let mut bits = [0; 5];
+            for (row, (cells, hole)) in rows.iter().zip(holes).enumerate().skip(stack_top) {
+                bits[row / 4] |= ((cells | 0x3F0) & !(1 << hole)) << (row % 4 * 16);
+            }
+            let board = GameGrids::from_bits(bits);
+            let brick = Brick(shape, state);
+            prop_assume!(board.brick_pos_valid(&brick, Vec2(x, 2), false));
+            let mut y = 2;
+            while board.brick_pos_valid(&brick, Vec2(x, y + 1), false) {
+                y += 1;
+            }
+
+            let mut grids = board.clone();
+            let mut undo = grids.make(&brick, Vec2(x, y));
+            let mut placed = board.clone();
+            placed.place_teris_brick(&brick, Vec2(x, y));
+            prop_assert_eq!(&grids, &placed);
+
+            let count = grids.clear_full_rows(&mut undo).unwrap();
+            prop_assert_eq!(count, undo.cleared().len());
+            prop_assert!((0..20).all(|row| !grids.is_full_row(row)));
+            grids.unmake(&undo);
+            prop_assert_eq!(grids, board);
+        }
+
+        #[test]
+        fn insert_row_undoes_remove_row(bits in prop::array::uniform5(any::<u64>()), y in 0..20i8) {
+            let board = GameGrids::from_bits(bits.map(|bits| bits & 0x03FF_03FF_03FF_03FF));
+            let mut grids = board.clone();
+            let row = grids.remove_row(y);
+            prop_assert_eq!(grids.get_row(0), 0);
+            grids.insert_row(y, row);
+            prop_assert_eq!(grids, board);
+        }
+    }
+}
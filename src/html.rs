@@ -0,0 +1,224 @@
+//! A replay player in one HTML file.
+//!
+//! The page embeds game.config.js and game.core.js and drives a `Tetris`
+//! instance with the op sequence, so the score it shows is the one of the
+//! original engine. The score `Replay`
+//! computes is embedded as well and the page reports whether both agree.
+
+use std::io::{self, Write};
+
+use crate::{
+    op::{self, GameOP, GameOPStr},
+    replay::{FrameStep, Replay},
+};
+
+const GAME_CONFIG_JS: &str = include_str!("game.config.js");
+const GAME_CORE_JS: &str = include_str!("game.core.js");
+
+const PLAYER_JS: &str = r#"(() => {
+    const opSequence = document.getElementById('op-sequence').textContent.trim();
+    const ops = opSequence.split(',').map((op) => op.trim()).filter((op) => op);
+    const expectedScore = +document.body.dataset.expectedScore;
+    const canvas = document.getElementById('board');
+    const ctx = canvas.getContext('2d');
+    const $ = (id) => document.getElementById(id);
+    let game, index, ended, timer;
+
+    function reset() {
+        game = new Tetris({});
+        game.initGrids();
+        game.setStatus('running');
+        index = 0;
+        ended = '';
+        draw();
+    }
+
+    // Apply the next op like the web game does, returns false once the replay ended.
+    function step() {
+        if (ended) return false;
+        if (index >= ops.length) return finish('end of the op sequence');
+        const op = ops[index++];
+        const { type, count } = game.getOpInfo(op);
+        switch (type) {
+            case 'N':
+                if (game.brickCount > 0) {
+                    const { topTouched, isRoundLimited } = game.update();
+                    if (topTouched) return finish('the stack touched the top');
+                    if (isRoundLimited) return finish('all bricks placed');
+                }
+                if (!game.initBrick().isValid) return finish('no room for a new brick');
+                return true;
+            case 'L':
+                game.move('left', count);
+                return true;
+            case 'R':
+                game.move('right', count);
+                return true;
+            case 'D':
+                game.move('down', count);
+                return true;
+            case 'C':
+                for (let i = 0; i < count; i++) game.rotate();
+                return true;
+            default:
+                return finish(`invalid op ${op}`);
+        }
+    }
+
+    function finish(reason) {
+        ended = reason;
+        stop();
+        return false;
+    }
+
+    function draw() {
+        const { width, height, row, col } = game.gridConfig;
+        const [w, h] = [width / col, height / row];
+        ctx.fillStyle = '#101010';
+        ctx.fillRect(0, 0, width, height);
+        const cell = (x, y, color) => {
+            ctx.fillStyle = color;
+            ctx.fillRect(x * w, y * h, w - 1, h - 1);
+        };
+        game.grids.forEach((cells, y) => cells.forEach((color, x) => cell(x, y, color || '#282828')));
+        if (game.curBrickInfo.pos) {
+            game.curBrickInfo.pos.forEach(([x, y]) => y >= 0 && cell(x, y, '#7cffb8'));
+        }
+        $('score').textContent = game.score;
+        $('bricks').textContent = game.brickCount;
+        $('progress').textContent = `${index} / ${ops.length}`;
+        $('status').textContent = ended ? `Ended: ${ended}` : '';
+        if (ended || index >= ops.length) {
+            const matches = game.score === expectedScore;
+            $('check').textContent = `Score ${game.score} ${matches ? 'matches' : 'differs from'} the expected ${expectedScore}.`;
+            $('check').className = matches ? 'ok' : 'bad';
+        } else {
+            $('check').textContent = '';
+        }
+    }
+
+    function tick() {
+        for (let i = 0; i < +$('speed').value; i++) {
+            if (!step()) break;
+        }
+        draw();
+    }
+
+    function stop() {
+        clearInterval(timer);
+        timer = undefined;
+        $('play').textContent = 'Play';
+    }
+
+    $('play').onclick = () => {
+        if (timer) return stop();
+        timer = setInterval(tick, 30);
+        $('play').textContent = 'Pause';
+    };
+    $('step').onclick = () => {
+        stop();
+        step();
+        draw();
+    };
+    $('brick').onclick = () => {
+        stop();
+        const bricks = game.brickCount;
+        while (game.brickCount === bricks && step());
+        draw();
+    };
+    $('end').onclick = () => {
+        stop();
+        while (step());
+        draw();
+    };
+    $('reset').onclick = () => {
+        stop();
+        reset();
+    };
+    reset();
+})();
+"#;
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+body { background: #1c1c1c; color: #ddd; font-family: monospace; display: flex; gap: 24px; padding: 24px; }
+canvas { border: 1px solid #444; }
+button { font-family: monospace; margin: 2px 0; }
+.ok { color: #7cffb8; }
+.bad { color: #ff7c7c; }
+</style>
+</head>
+<body data-expected-score="{{EXPECTED_SCORE}}">
+<canvas id="board" width="200" height="400"></canvas>
+<div>
+<h3>{{TITLE}}</h3>
+<p>Score <span id="score"></span><br>Bricks <span id="bricks"></span><br>Ops <span id="progress"></span></p>
+<p><button id="play">Play</button> <button id="step">Op</button> <button id="brick">Brick</button> <button id="end">End</button> <button id="reset">Reset</button></p>
+<p>Ops per frame <input id="speed" type="range" min="1" max="200" value="4"></p>
+<p id="status"></p>
+<p id="check"></p>
+</div>
+<script type="text/plain" id="op-sequence">{{OPS}}</script>
+<script>
+{{GAME_CONFIG_JS}}
+</script>
+<script>
+{{GAME_CORE_JS}}
+</script>
+<script>
+{{PLAYER_JS}}
+</script>
+</body>
+</html>
+"#;
+
+/// Write a page that replays `ops` with game.core.js.
+pub fn write_player(mut ops: Vec<GameOP>, title: &str, mut output: impl Write) -> io::Result<()> {
+    // The page replays with game.core.js, which only locks a brick on `N`.
+    op::lock_last_brick(&mut ops);
+    let op_sequence = ops.to_op_string();
+    let expected_score = Replay::new(ops, FrameStep::PerBrick)?
+        .last()
+        .map_or(0, |frame| frame.score);
+    let title = title
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let page = TEMPLATE
+        .replace("{{TITLE}}", &title)
+        .replace("{{EXPECTED_SCORE}}", &expected_score.to_string())
+        .replace("{{OPS}}", &op_sequence)
+        .replace("{{PLAYER_JS}}", PLAYER_JS)
+        .replace("{{GAME_CONFIG_JS}}", GAME_CONFIG_JS)
+        .replace("{{GAME_CORE_JS}}", GAME_CORE_JS);
+    output.write_all(page.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        game::{greedy_playout, GameState},
+        op::GameOPStr,
+    };
+
+    use super::{write_player, GAME_CONFIG_JS, GAME_CORE_JS};
+
+    #[test]
+    fn test_write_player() {
+        let ops = greedy_playout(&GameState::initial_state(), 3).get_op_sequence();
+        let mut page = Vec::new();
+        write_player(ops.clone(), "<run>", &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+
+        assert!(page.contains(&format!(">{},N</script>", ops.to_op_string())));
+        assert!(page.contains(GAME_CONFIG_JS) && page.contains(GAME_CORE_JS));
+        assert!(page.contains("<title>&lt;run&gt;</title>"));
+        assert!(!page.contains("{{"));
+        // The embedded scripts must not close their script element early.
+        assert_eq!(page.matches("<script").count(), page.matches("</script>").count());
+    }
+}
@@ -0,0 +1,173 @@
+//! Replays run by the bundled game.core.js in the boa interpreter, to check
+//! scores against the official rules without a browser.
+
+use std::io;
+
+use boa_engine::{js_string, Context, JsError, JsObject, JsValue, Source};
+
+use crate::op::{self, GameOP};
+
+const GAME_CONFIG_JS: &str = include_str!("game.config.js");
+const GAME_CORE_JS: &str = include_str!("game.core.js");
+
+/// Outcome of a replay in game.core.js.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsReplay {
+    pub score: u32,
+    pub brick_count: usize,
+    /// Why the game ended before the op sequence did.
+    pub ended: Option<String>,
+}
+
+fn js_error(err: JsError) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+/// A `Tetris` instance of game.core.js.
+pub struct JsGame {
+    context: Context,
+    game: JsObject,
+}
+
+impl JsGame {
+    pub fn new() -> io::Result<Self> {
+        let mut context = Context::default();
+        // Both files register themselves on `window`.
+        for code in ["var window = globalThis;", GAME_CONFIG_JS, GAME_CORE_JS] {
+            context.eval(Source::from_bytes(code)).map_err(js_error)?;
+        }
+        let game = context
+            .eval(Source::from_bytes(
+                "const game = new Tetris({}); game.initGrids(); game.setStatus('running'); game",
+            ))
+            .map_err(js_error)?
+            .as_object()
+            .cloned()
+            .ok_or_else(|| io::Error::other("game.core.js did not create a Tetris instance"))?;
+        Ok(Self { context, game })
+    }
+
+    fn call(&mut self, method: &str, args: &[JsValue]) -> io::Result<JsValue> {
+        let function = self.game.get(js_string!(method), &mut self.context).map_err(js_error)?;
+        let function = function
+            .as_callable()
+            .ok_or_else(|| io::Error::other(format!("Tetris.{} is not a function", method)))?;
+        function
+            .call(&self.game.clone().into(), args, &mut self.context)
+            .map_err(js_error)
+    }
+
+    fn get(&mut self, object: &JsObject, property: &str) -> io::Result<JsValue> {
+        object.get(js_string!(property), &mut self.context).map_err(js_error)
+    }
+
+    fn number(&mut self, property: &str) -> io::Result<f64> {
+        let game = self.game.clone();
+        let value = self.get(&game, property)?;
+        value.to_number(&mut self.context).map_err(js_error)
+    }
+
+    fn flag(&mut self, value: &JsValue, property: &str) -> io::Result<bool> {
+        match value.as_object() {
+            Some(object) => Ok(self.get(object, property)?.to_boolean()),
+            None => Ok(false),
+        }
+    }
+
+    pub fn score(&mut self) -> io::Result<u32> {
+        Ok(self.number("score")? as u32)
+    }
+
+    pub fn brick_count(&mut self) -> io::Result<usize> {
+        Ok(self.number("brickCount")? as usize)
+    }
+
+    /// Apply `op` the way the web game does. Returns why the game ended, if it did.
+    pub fn apply(&mut self, op: GameOP) -> io::Result<Option<&'static str>> {
+        let moved = |dir: &str, steps: i8| [js_string!(dir).into(), JsValue::from(steps as i32)];
+        match op {
+            GameOP::New => {
+                if self.brick_count()? > 0 {
+                    let result = self.call("update", &[])?;
+                    if self.flag(&result, "topTouched")? {
+                        return Ok(Some("the stack touched the top"));
+                    }
+                    if self.flag(&result, "isRoundLimited")? {
+                        return Ok(Some("all bricks placed"));
+                    }
+                }
+                let result = self.call("initBrick", &[])?;
+                if !self.flag(&result, "isValid")? {
+                    return Ok(Some("no room for a new brick"));
+                }
+            }
+            GameOP::Left(steps) => {
+                self.call("move", &moved("left", steps))?;
+            }
+            GameOP::Right(steps) => {
+                self.call("move", &moved("right", steps))?;
+            }
+            GameOP::Down(steps) => {
+                self.call("move", &moved("down", steps))?;
+            }
+            GameOP::Rotate(steps) => {
+                for _ in 0..steps {
+                    self.call("rotate", &[])?;
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Replay `ops` in game.core.js until they run out or the game ends. The last
+/// brick is locked like `replay::Replay` does.
+pub fn replay(ops: &[GameOP]) -> io::Result<JsReplay> {
+    let mut ops = ops.to_vec();
+    op::lock_last_brick(&mut ops);
+    let mut game = JsGame::new()?;
+    let mut ended = None;
+    for op in ops {
+        if let Some(reason) = game.apply(op)? {
+            ended = Some(reason.to_string());
+            break;
+        }
+    }
+    Ok(JsReplay {
+        score: game.score()?,
+        brick_count: game.brick_count()?,
+        ended,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        game::{greedy_playout, GameState},
+        op::{parse_op_sequence, GameOP},
+    };
+
+    use super::replay;
+
+    #[test]
+    fn test_replay_matches_rust() {
+        let mut state = GameState::initial_state();
+        while state.score == 0 {
+            state = greedy_playout(&state, 1);
+        }
+        let mut ops = state.get_op_sequence();
+        // Lock the last brick so its clear is scored.
+        ops.push(GameOP::New);
+        let result = replay(&ops).unwrap();
+        assert_eq!(result.score, state.score);
+        assert_eq!(result.brick_count, state.brick_count + 1);
+        assert_eq!(result.ended, None);
+    }
+
+    #[test]
+    fn test_invalid_ops_are_ignored() {
+        // L9 would leave the board and is dropped, R2 still applies.
+        let result = replay(&parse_op_sequence("N,L9,R2,D18,N").unwrap()).unwrap();
+        assert_eq!((result.score, result.brick_count), (0, 2));
+    }
+}
@@ -0,0 +1,27 @@
+pub mod grid;
+pub mod game;
+pub mod brick;
+pub mod vec2;
+pub mod random;
+pub mod op;
+pub mod game_play;
+pub mod auto;
+pub mod archive;
+pub mod budget;
+pub mod dashboard;
+pub mod distributed;
+pub mod endgame;
+pub mod fixed_heap;
+pub mod utils;
+pub mod game_io;
+pub mod render;
+pub mod replay;
+pub mod selection;
+pub mod explain;
+pub mod analyze;
+pub mod export;
+pub mod html;
+#[cfg(feature = "serde")]
+pub mod serial;
+#[cfg(feature = "js")]
+pub mod js;
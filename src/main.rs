@@ -1,25 +1,412 @@
+use std::{io, process, thread, time::Duration};
 
+use tetris_auto::{
+    analyze::{self, DeepSearch},
+    auto::{self, TetrisAuto},
+    budget::{SearchBudget, SearchConfig},
+    distributed,
+    explain::{self, Explanation},
+    export::{self, ExportFormat, ExportOptions},
+    game::{GameState, MAX_BRICKS_COUNT},
+    game_io::{self, GameRenderer, GetInput},
+    game_play, html, op,
+    render::RendererKind,
+    replay,
+};
+#[cfg(feature = "js")]
+use tetris_auto::js;
 
-use auto::TetrisAuto;
-use game_io::GetInput;
-use game_play::Game;
+const DEFAULT_THREADS: usize = 10;
+/// Op-sequence file `play` resumes from and saves to.
+const DEFAULT_PLAY_FILE: &str = "op_sequence_play";
+/// Placements `explain` compares by default.
+const DEFAULT_EXPLAIN_TOP: usize = 5;
+/// How often the main thread checks for an interrupt while the search runs.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
-pub mod grid;
-pub mod game;
-pub mod brick;
-pub mod vec2;
-pub mod random;
-pub mod op;
-pub mod game_play;
-pub mod auto;
-pub mod fixed_heap;
-pub mod utils;
-pub mod game_io;
+struct Options {
+    threads: usize,
+    cooperative: bool,
+    listen: Option<String>,
+    connect: Option<String>,
+    checkpoint: Option<String>,
+    archive: Option<String>,
+    render: RendererKind,
+    search: SearchConfig,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        threads: DEFAULT_THREADS,
+        cooperative: false,
+        listen: None,
+        connect: None,
+        checkpoint: None,
+        archive: None,
+        render: RendererKind::detect(),
+        search: SearchConfig::default(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--threads" => options.threads = parse_value(arg, value()?)?,
+            "--coop" => options.cooperative = true,
+            "--listen" => options.listen = Some(value()?.clone()),
+            "--connect" => options.connect = Some(value()?.clone()),
+            "--checkpoint" => options.checkpoint = Some(value()?.clone()),
+            "--archive" => options.archive = Some(value()?.clone()),
+            "--render" => options.render = value()?.parse()?,
+            "--seed" => options.search.seed = Some(parse_value(arg, value()?)?),
+            "--budget" => {
+                options.search.budget = SearchBudget::WallClock(Duration::from_secs_f64(parse_value(arg, value()?)?))
+            }
+            "--cpu-budget" => {
+                options.search.budget = SearchBudget::Cpu(Duration::from_secs_f64(parse_value(arg, value()?)?))
+            }
+            "--min-beam" => options.search.min_beam_width = parse_value(arg, value()?)?,
+            "--max-beam" => options.search.max_beam_width = parse_value(arg, value()?)?,
+            "--endgame" => options.search.endgame_depth = parse_value(arg, value()?)?,
+            "--selection" => options.search.selection = value()?.parse()?,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    if options.cooperative {
+        // The cooperative search neither serves workers nor resumes or archives states.
+        let unsupported = [
+            ("--listen", options.listen.is_some()),
+            ("--connect", options.connect.is_some()),
+            ("--checkpoint", options.checkpoint.is_some()),
+            ("--archive", options.archive.is_some()),
+        ];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, given)| *given) {
+            return Err(format!("--coop can't be combined with {}", flag));
+        }
+    }
+    Ok(options)
+}
+
+fn parse_export_options(args: &[String]) -> Result<(String, String, ExportOptions), String> {
+    let mut paths = Vec::new();
+    let mut format = None;
+    let mut options = ExportOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--format" => format = Some(value()?.parse()?),
+            "--per" => options.step = value()?.parse()?,
+            "--skip" => options.skip = parse_value(arg, value()?)?,
+            "--cell" => options.cell_size = parse_value(arg, value()?)?,
+            "--delay" => options.frame_delay = Duration::from_millis(parse_value(arg, value()?)?),
+            "--no-score" => options.score_overlay = false,
+            _ if arg.starts_with("--") => return Err(format!("unknown argument: {}", arg)),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    match &paths[..] {
+        [input, output] => {
+            options.format = format.unwrap_or_else(|| ExportFormat::from_path(output));
+            Ok((input.clone(), output.clone(), options))
+        }
+        _ => Err("expected an op-sequence file and an output path".to_string()),
+    }
+}
+
+fn parse_train_options(args: &[String]) -> Result<(usize, usize, u64), String> {
+    let (mut bricks, mut width, mut seed) = (auto::TRAIN_BRICKS, auto::TRAIN_WIDTH, auto::TRAIN_SEED);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--bricks" => bricks = parse_value(arg, value)?,
+            "--width" => width = parse_value(arg, value)?,
+            "--seed" => seed = parse_value(arg, value)?,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok((bricks, width, seed))
+}
+
+/// Where `explain` and `analyze` take their board from.
+enum Position {
+    /// An op-sequence file, after the given number of bricks or at its end.
+    Replay(String, Option<usize>),
+    /// A file written by `GameState::to_ascii`.
+    Board(String),
+}
+
+/// Parse the position `explain` and `analyze` look at, handing their other
+/// options and values to `option`.
+fn parse_position_options(
+    args: &[String],
+    mut option: impl FnMut(&str, &str) -> Result<(), String>,
+) -> Result<Position, String> {
+    let (mut input, mut board, mut brick) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--brick" => brick = Some(parse_value(arg, value()?)?),
+            "--board" => board = Some(value()?.clone()),
+            _ if arg.starts_with("--") => option(arg, value()?)?,
+            _ if input.is_some() => return Err(format!("unknown argument: {}", arg)),
+            _ => input = Some(arg.clone()),
+        }
+    }
+    match (input, board) {
+        (Some(input), None) => Ok(Position::Replay(input, brick)),
+        (None, Some(board)) if brick.is_none() => Ok(Position::Board(board)),
+        _ => Err("expected either an op-sequence file or --board FILE".to_string()),
+    }
+}
+
+fn load_position(position: &Position) -> io::Result<GameState> {
+    match position {
+        Position::Replay(input, brick) => load_replay_position(input, *brick),
+        Position::Board(input) => GameState::from_ascii(&std::fs::read_to_string(input)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+    }
+}
+
+fn load_position_or_exit(position: &Position) -> GameState {
+    load_position(position).unwrap_or_else(|err| {
+        let (Position::Replay(input, _) | Position::Board(input)) = position;
+        eprintln!("{}: {}", input, err);
+        process::exit(1);
+    })
+}
+
+/// The state of the op-sequence file `input` once `brick` bricks are placed,
+/// or at its end.
+fn load_replay_position(input: &str, brick: Option<usize>) -> io::Result<GameState> {
+    let mut replay = replay::Replay::load(input, replay::FrameStep::PerBrick)?;
+    for frame in replay.by_ref() {
+        if Some(frame.brick_count) == brick {
+            break;
+        }
+    }
+    let state = replay.game().state();
+    match brick {
+        Some(brick) if brick != state.brick_count => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the game ends after {} bricks", state.brick_count),
+        )),
+        _ => Ok(state.clone()),
+    }
+}
+
+/// Replay the op-sequence file `input` in game.core.js and compare the score
+/// with `replay::Replay`. Returns the exit code.
+#[cfg(feature = "js")]
+fn verify(input: &str) -> i32 {
+    let checked = std::fs::read_to_string(input)
+        .and_then(|text| op::parse_op_sequence(&text))
+        .and_then(|ops| {
+            let expected = replay::Replay::new(ops.clone(), replay::FrameStep::PerBrick)?
+                .last()
+                .map_or(0, |frame| frame.score);
+            Ok((js::replay(&ops)?, expected))
+        });
+    match checked {
+        Ok((result, expected)) => {
+            println!("game.core.js: score {}, {} bricks", result.score, result.brick_count);
+            if let Some(reason) = &result.ended {
+                println!("game.core.js: game ended early, {}", reason);
+            }
+            println!("tetris-auto:  score {}", expected);
+            if result.score != expected {
+                return 1;
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("{}: {}", input, err);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "js"))]
+fn verify(_input: &str) -> i32 {
+    eprintln!("verify needs the js feature, build with --features js");
+    1
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
 
 #[allow(warnings)]
 fn main() {
-    let game = Game::new();
-    // game.start();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("play") {
+        let path = args.get(1).map_or(DEFAULT_PLAY_FILE, String::as_str);
+        let game = match std::path::Path::new(path).exists() {
+            true => game_play::Game::load(path).unwrap_or_else(|err| {
+                eprintln!("{}: {}", path, err);
+                process::exit(1);
+            }),
+            false => game_play::Game::new(),
+        };
+        // Reading single key presses needs the raw mode of the terminal renderer.
+        game.start(path, &mut GameRenderer::new());
+        return;
+    }
+    if args.first().map(String::as_str) == Some("export") {
+        let (input, output, options) = parse_export_options(&args[1..]).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            eprintln!("usage: tetris-auto export FILE OUTPUT [--format gif|svg|png] [--per brick|op] [--skip N]");
+            eprintln!("                          [--cell PIXELS] [--delay MS] [--no-score]");
+            process::exit(1);
+        });
+        let written = std::fs::read_to_string(&input)
+            .and_then(|text| op::parse_op_sequence(&text))
+            .and_then(|ops| export::export(ops, &output, &options));
+        match written {
+            Ok(count) => println!("Wrote {} frames to {}", count, output),
+            Err(err) => {
+                eprintln!("{}: {}", input, err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("export-html") {
+        let input = args.get(1).unwrap_or_else(|| {
+            eprintln!("usage: tetris-auto export-html FILE [OUTPUT]");
+            process::exit(1);
+        });
+        let output = args.get(2).cloned().unwrap_or_else(|| format!("{}.html", input));
+        let written = std::fs::read_to_string(input)
+            .and_then(|text| op::parse_op_sequence(&text))
+            .and_then(|ops| html::write_player(ops, input, std::fs::File::create(&output)?));
+        match written {
+            Ok(()) => println!("Wrote {}", output),
+            Err(err) => {
+                eprintln!("{}: {}", input, err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("train-profile") {
+        let (bricks, width, seed) = parse_train_options(&args[1..]).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            eprintln!("usage: tetris-auto train-profile [--bricks N] [--width N] [--seed N]");
+            process::exit(1);
+        });
+        let started = std::time::Instant::now();
+        let state = TetrisAuto::train_profile(bricks, width, seed);
+        println!(
+            "Placed {} bricks, score {} in {:.1}s",
+            state.brick_count,
+            state.score,
+            started.elapsed().as_secs_f64()
+        );
+        return;
+    }
+    if args.first().map(String::as_str) == Some("explain") {
+        let mut top = DEFAULT_EXPLAIN_TOP;
+        let position = parse_position_options(&args[1..], |arg, value| {
+            match arg {
+                "--top" => top = parse_value(arg, value)?,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+            Ok(())
+        });
+        let position = position.unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            eprintln!("usage: tetris-auto explain (FILE [--brick N] | --board FILE) [--top N]");
+            process::exit(1);
+        });
+        let state = load_position_or_exit(&position);
+        for line in Explanation::of_state(&state).lines() {
+            println!("{}", line);
+        }
+        let top = explain::top_placements(&state, top);
+        if !top.is_empty() {
+            println!();
+            println!("Best {} placements of the next brick", top.len());
+            for line in explain::compare_lines(&top) {
+                println!("{}", line);
+            }
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("analyze") {
+        let (mut depth, mut width) = (None, auto::HINT_WIDTH);
+        let position = parse_position_options(&args[1..], |arg, value| {
+            match arg {
+                "--depth" => depth = Some(parse_value(arg, value)?),
+                "--width" => width = parse_value(arg, value)?,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+            Ok(())
+        });
+        let position = position.unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            eprintln!("usage: tetris-auto analyze (FILE [--brick N] | --board FILE) [--depth N] [--width N]");
+            process::exit(1);
+        });
+        let state = load_position_or_exit(&position);
+        let deep_search = depth.map(|depth| DeepSearch { depth, width });
+        for line in analyze::analyze(&state, deep_search).lines() {
+            println!("{}", line);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("verify") {
+        let input = args.get(1).unwrap_or_else(|| {
+            eprintln!("usage: tetris-auto verify FILE");
+            process::exit(1);
+        });
+        process::exit(verify(input));
+    }
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("usage: tetris-auto play [FILE]");
+            eprintln!("       tetris-auto export FILE OUTPUT [--format gif|svg|png] [--per brick|op] [--skip N]");
+            eprintln!("       tetris-auto export-html FILE [OUTPUT]");
+            eprintln!("       tetris-auto explain (FILE [--brick N] | --board FILE) [--top N]");
+            eprintln!("       tetris-auto analyze (FILE [--brick N] | --board FILE) [--depth N] [--width N]");
+            eprintln!("       tetris-auto verify FILE");
+            eprintln!("       tetris-auto train-profile [--bricks N] [--width N] [--seed N]");
+            eprintln!("       tetris-auto [--threads N] [--coop | --listen ADDR | --connect ADDR] [--checkpoint FILE] [--archive FILE]");
+            eprintln!("                   [--render terminal|ansi|text|headless]");
+            eprintln!("                   [--budget SECS | --cpu-budget SECS] [--min-beam N] [--max-beam N] [--endgame N] [--seed N]");
+            eprintln!("                   [--selection jitter|niche:N|parent-cap:N|novelty:W|tournament:K]");
+            process::exit(1);
+        }
+    };
+
+    if let Some(addr) = options.connect {
+        let workers = (0..options.threads.max(1))
+            .map(|_| {
+                let addr = addr.clone();
+                thread::spawn(move || distributed::run_worker(addr))
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            if let Err(err) = worker.join().unwrap() {
+                eprintln!("worker: {}", err);
+            }
+        }
+        return;
+    }
 
     // thread::spawn(|| {
     //     let keys = stdin().keys();
@@ -31,13 +418,39 @@ fn main() {
     //     }
     // });
 
-    let (mut kill_bus, join) = TetrisAuto::run_continuous(10);
+    let (mut kill_bus, join) = match options.cooperative {
+        true => TetrisAuto::run_cooperative(options.threads, options.search, options.render.create()),
+        false => {
+            let checkpoint = match options.checkpoint {
+                Some(path) => distributed::load_checkpoint(&path).unwrap_or_else(|err| {
+                    eprintln!("{}: {}", path, err);
+                    process::exit(1);
+                }),
+                None => Vec::new(),
+            };
+            let listen = options.listen.as_deref();
+            let renderer = options.render.create();
+            TetrisAuto::run_continuous(options.threads, options.search, listen, checkpoint, options.archive, renderer)
+                .unwrap_or_else(|err| {
+                    eprintln!("failed to start coordinator: {}", err);
+                    process::exit(1);
+                })
+        }
+    };
     let mut input = game_io::GameInput::new();
-    loop {
+    while !join.is_finished() {
         if input.try_get_interrupt().is_ok() {
             kill_bus.broadcast(());
             break;
         }
+        thread::sleep(INTERRUPT_POLL_INTERVAL);
+    }
+    if let Ok(state) = join.join() {
+        if state.brick_count < MAX_BRICKS_COUNT {
+            eprintln!(
+                "No complete game found, the best one placed {} bricks with score {}",
+                state.brick_count, state.score
+            );
+        }
     }
-    join.join();
 }
@@ -1,4 +1,7 @@
-#[derive(Clone, Copy)]
+use std::io;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GameOP {
     New,
     Left(i8),
@@ -8,7 +11,7 @@ pub enum GameOP {
 }
 
 pub trait GameOPStr {
-    fn to_op_string(self) -> String;    
+    fn to_op_string(self) -> String;
 }
 
 impl GameOPStr for &[GameOP] {
@@ -16,7 +19,7 @@ impl GameOPStr for &[GameOP] {
         let mut outputs = Vec::with_capacity(self.len());
         for op in self {
             match op {
-                GameOP::New => outputs.push(format!("N")),
+                GameOP::New => outputs.push("N".to_string()),
                 GameOP::Left(x) => outputs.push(format!("L{}", x)),
                 GameOP::Right(x) => outputs.push(format!("R{}", x)),
                 GameOP::Down(y) => outputs.push(format!("D{}", y)),
@@ -27,3 +30,98 @@ impl GameOPStr for &[GameOP] {
         outputs.join(",")
     }
 }
+
+/// Parse an op sequence as written by `to_op_string`, e.g. `N,L3,C1,D18`.
+pub fn parse_op_sequence(text: &str) -> io::Result<Vec<GameOP>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|op| !op.is_empty())
+        .map(|op| {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid op: {}", op));
+            if !op.is_char_boundary(1) {
+                return Err(invalid());
+            }
+            let (kind, count) = op.split_at(1);
+            let steps = || count.parse::<i8>().map_err(|_| invalid());
+            match kind {
+                "N" if count.is_empty() => Ok(GameOP::New),
+                "L" => Ok(GameOP::Left(steps()?)),
+                "R" => Ok(GameOP::Right(steps()?)),
+                "D" => Ok(GameOP::Down(steps()?)),
+                "C" => Ok(GameOP::Rotate(steps()?)),
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+/// Append `op` to a record the way `trackOp` in game.core.js does: an op of the
+/// same kind as the previous one is merged into it, except for `New`.
+pub fn track_op(ops: &mut Vec<GameOP>, op: GameOP) {
+    let merged = match (ops.last(), op) {
+        (Some(GameOP::Left(a)), GameOP::Left(b)) => a.checked_add(b).map(GameOP::Left),
+        (Some(GameOP::Right(a)), GameOP::Right(b)) => a.checked_add(b).map(GameOP::Right),
+        (Some(GameOP::Down(a)), GameOP::Down(b)) => a.checked_add(b).map(GameOP::Down),
+        (Some(GameOP::Rotate(a)), GameOP::Rotate(b)) => a.checked_add(b).map(GameOP::Rotate),
+        _ => None,
+    };
+    match merged {
+        Some(merged) => *ops.last_mut().unwrap() = merged,
+        None => ops.push(op),
+    }
+}
+
+/// Append the `N` that locks the last brick, solver output ends right after
+/// its moves. Sequences that already end with `N` are left alone.
+pub fn lock_last_brick(ops: &mut Vec<GameOP>) {
+    if ops.last().is_some_and(|op| *op != GameOP::New) {
+        ops.push(GameOP::New);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lock_last_brick, parse_op_sequence, track_op, GameOP, GameOPStr};
+
+    #[test]
+    fn test_track_op() {
+        let mut ops = Vec::new();
+        for op in [
+            GameOP::New,
+            GameOP::Left(1),
+            GameOP::Left(1),
+            GameOP::Left(1),
+            GameOP::Rotate(1),
+            GameOP::Down(17),
+            GameOP::Down(1),
+            GameOP::New,
+            GameOP::New,
+        ] {
+            track_op(&mut ops, op);
+        }
+        assert_eq!(ops.to_op_string(), "N,L3,C1,D18,N,N");
+    }
+
+    #[test]
+    fn test_parse_op_sequence() {
+        let ops = parse_op_sequence("N,L3, C1,D18,N\n").unwrap();
+        assert_eq!(ops, vec![GameOP::New, GameOP::Left(3), GameOP::Rotate(1), GameOP::Down(18), GameOP::New]);
+        assert_eq!(ops.to_op_string(), "N,L3,C1,D18,N");
+        assert!(parse_op_sequence("").unwrap().is_empty());
+        assert!(parse_op_sequence("N,X1").is_err());
+        assert!(parse_op_sequence("N2").is_err());
+        assert!(parse_op_sequence("L").is_err());
+    }
+
+    #[test]
+    fn test_lock_last_brick() {
+        let mut ops = parse_op_sequence("N,L3,D18").unwrap();
+        lock_last_brick(&mut ops);
+        assert_eq!(ops.to_op_string(), "N,L3,D18,N");
+        lock_last_brick(&mut ops);
+        assert_eq!(ops.to_op_string(), "N,L3,D18,N");
+        let mut ops = Vec::new();
+        lock_last_brick(&mut ops);
+        assert!(ops.is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+//! Renderer backends that don't need a terminal in raw mode.
+//!
+//! `TextRenderer` and `AnsiRenderer` draw the same layout as the terminal
+//! renderer into a `Canvas`, which is presented as a string frame or as
+//! 256-color ANSI output on every `flush`.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    str::FromStr,
+};
+
+use crate::{
+    brick::Brick,
+    game::GameState,
+    game_io::{GameRenderer, RenderGame},
+    vec2::Vec2,
+};
+
+/// Color of the ghost piece, a gray from the 256-color palette.
+const GHOST_COLOR: u8 = 244;
+/// Color of the placement suggested by a hint, a yellow.
+const HINT_COLOR: u8 = 220;
+
+lazy_static::lazy_static! {
+    /// The brick colors of game.config.js as 256-color palette indices.
+    pub static ref BRICK_COLORS: Vec<u8> = parse_colors(include_str!("game.config.js"))
+        .iter()
+        .map(|rgb| ansi_256(*rgb))
+        .collect();
+}
+
+/// Read the `colors: ['#rrggbb', ...]` entry of game.config.js.
+pub fn parse_colors(config: &str) -> Vec<[u8; 3]> {
+    let list = config
+        .split_once("colors:")
+        .and_then(|(_, rest)| rest.split_once('['))
+        .and_then(|(_, rest)| rest.split_once(']'))
+        .map_or("", |(list, _)| list);
+    list.split(',')
+        .filter_map(|color| {
+            let hex = color.trim().trim_matches(|c| c == '\'' || c == '"').strip_prefix('#')?;
+            let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+            Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+        })
+        .collect()
+}
+
+/// Nearest color of the 6x6x6 cube of the 256-color palette.
+pub fn ansi_256(rgb: [u8; 3]) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |value: u8| {
+        (0..LEVELS.len())
+            .min_by_key(|idx| (LEVELS[*idx] as i32 - value as i32).abs())
+            .unwrap() as u8
+    };
+    16 + 36 * level(rgb[0]) + 6 * level(rgb[1]) + level(rgb[2])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RendererKind {
+    /// termion in raw mode, also needed to read keys in `play`.
+    Terminal,
+    Ansi,
+    Text,
+    Headless,
+}
+
+impl RendererKind {
+    /// The terminal renderer when stdout is a terminal, nothing otherwise.
+    pub fn detect() -> Self {
+        match io::stdout().is_terminal() {
+            true => Self::Terminal,
+            false => Self::Headless,
+        }
+    }
+
+    pub fn create(self) -> Box<dyn RenderGame + Send> {
+        match self {
+            Self::Terminal => Box::new(GameRenderer::new()),
+            Self::Ansi => Box::new(AnsiRenderer::new(AnsiOutput(Box::new(io::stdout())))),
+            Self::Text => Box::new(TextRenderer::new(TextFrames::to_writer(Box::new(io::stdout())))),
+            Self::Headless => Box::new(HeadlessRenderer),
+        }
+    }
+}
+
+impl FromStr for RendererKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "terminal" => Ok(Self::Terminal),
+            "ansi" => Ok(Self::Ansi),
+            "text" => Ok(Self::Text),
+            "headless" => Ok(Self::Headless),
+            _ => Err(format!("unknown renderer: {}", text)),
+        }
+    }
+}
+
+/// Draws nothing.
+pub struct HeadlessRenderer;
+
+impl RenderGame for HeadlessRenderer {
+    fn render_game(&mut self, _state: &GameState) {}
+    fn render_user_hint(&mut self) {}
+    fn render_brick(&mut self, _brick: &Brick, _pos: Vec2) {}
+    fn render_ghost(&mut self, _brick: &Brick, _pos: Vec2) {}
+    fn render_hint(&mut self, _brick: &Brick, _pos: Vec2) {}
+    fn render_next_brick(&mut self, _brick: &Brick) {}
+    fn render_message(&mut self, _message: &str) {}
+    fn render_lines(&mut self, _lines: &[String]) {}
+    fn flush(&mut self) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub color: Option<u8>,
+}
+
+const EMPTY_CELL: Cell = Cell { ch: ' ', color: None };
+
+/// A grid of characters with an optional 256-color palette index each, grown
+/// as needed.
+#[derive(Default)]
+pub struct Canvas {
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Canvas {
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    pub fn put(&mut self, x: usize, y: usize, ch: char, color: Option<u8>) {
+        if self.rows.len() <= y {
+            self.rows.resize(y + 1, Vec::new());
+        }
+        let row = &mut self.rows[y];
+        if row.len() <= x {
+            row.resize(x + 1, EMPTY_CELL);
+        }
+        row[x] = Cell { ch, color };
+    }
+
+    pub fn put_str(&mut self, x: usize, y: usize, text: &str) {
+        for (i, ch) in text.chars().enumerate() {
+            self.put(x + i, y, ch, None);
+        }
+    }
+
+    pub fn rows(&self) -> &[Vec<Cell>] {
+        &self.rows
+    }
+
+    /// The canvas as plain text, without trailing spaces.
+    pub fn to_text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Where a `CanvasRenderer` presents its frames.
+pub trait FrameSink {
+    fn present(&mut self, canvas: &Canvas);
+}
+
+/// Renders the layout of the terminal renderer into a `Canvas` and hands it
+/// to a `FrameSink` on `flush`.
+pub struct CanvasRenderer<S> {
+    canvas: Canvas,
+    brick_count: usize,
+    sink: S,
+}
+
+pub type TextRenderer = CanvasRenderer<TextFrames>;
+pub type AnsiRenderer = CanvasRenderer<AnsiOutput>;
+
+impl<S: FrameSink> CanvasRenderer<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            canvas: Canvas::default(),
+            brick_count: 0,
+            sink,
+        }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    fn color(&self, brick_count: usize) -> Option<u8> {
+        match BRICK_COLORS.len() {
+            0 => None,
+            len => Some(BRICK_COLORS[brick_count % len]),
+        }
+    }
+
+    fn put_brick(&mut self, brick: &Brick, pos: Vec2, ch: char, color: Option<u8>) {
+        for cell in brick.pos_with_center(pos) {
+            if let Vec2(0..=9, 0..=19) = cell {
+                self.canvas.put(cell.0 as usize, cell.1 as usize, ch, color);
+            }
+        }
+    }
+}
+
+impl<S: FrameSink> RenderGame for CanvasRenderer<S> {
+    fn render_game(&mut self, state: &GameState) {
+        self.canvas.clear();
+        self.brick_count = state.brick_count;
+        self.canvas.put_str(12, 2, &format!("Score: {}", state.score));
+        self.canvas.put_str(12, 3, &format!("SP Score: {}", state.sp_score));
+        self.canvas.put_str(12, 4, &format!("Bricks: {}", state.brick_count));
+        // The grid doesn't remember which brick filled a cell, settled cells
+        // take the first color.
+        let color = self.color(0);
+        for y in 0..20 {
+            for x in 0..10 {
+                match state.grids.get(Vec2(x, y)) {
+                    true => self.canvas.put(x as usize, y as usize, '*', color),
+                    false => self.canvas.put(x as usize, y as usize, ' ', None),
+                }
+            }
+            self.canvas.put(10, y as usize, '|', None);
+        }
+        for x in 0..10 {
+            self.canvas.put(x, 20, '-', None);
+        }
+    }
+
+    fn render_user_hint(&mut self) {
+        self.canvas.put_str(12, 5, "<Left|Right|Down>: Move");
+        self.canvas.put_str(12, 6, "<Up>: Rotate");
+        self.canvas.put_str(12, 7, "<Space>: Drop");
+        self.canvas.put_str(12, 8, "<u|r>: Undo/Redo");
+        self.canvas.put_str(12, 9, "<s|l>: Save/Load");
+        self.canvas.put_str(12, 10, "<h>: Hint");
+        self.canvas.put_str(12, 11, "<q>: Quit");
+    }
+
+    fn render_brick(&mut self, brick: &Brick, pos: Vec2) {
+        let color = self.color(self.brick_count);
+        self.put_brick(brick, pos, '*', color);
+    }
+
+    fn render_ghost(&mut self, brick: &Brick, pos: Vec2) {
+        self.put_brick(brick, pos, '.', Some(GHOST_COLOR));
+    }
+
+    fn render_hint(&mut self, brick: &Brick, pos: Vec2) {
+        self.put_brick(brick, pos, '+', Some(HINT_COLOR));
+    }
+
+    fn render_next_brick(&mut self, brick: &Brick) {
+        self.canvas.put_str(12, 13, "Next:");
+        let color = self.color(self.brick_count + 1);
+        for cell in brick.pos_with_center(Vec2(15, 16)) {
+            self.canvas.put(cell.0 as usize, cell.1 as usize, '*', color);
+        }
+    }
+
+    fn render_message(&mut self, message: &str) {
+        self.canvas.put_str(12, 19, message);
+    }
+
+    fn render_lines(&mut self, lines: &[String]) {
+        self.canvas.clear();
+        for (y, line) in lines.iter().enumerate() {
+            self.canvas.put_str(0, y, line);
+        }
+    }
+
+    fn flush(&mut self) {
+        self.sink.present(&self.canvas);
+    }
+}
+
+/// Frames as plain text, kept in memory or written out.
+#[derive(Default)]
+pub struct TextFrames {
+    output: Option<Box<dyn Write + Send>>,
+    frames: Vec<String>,
+}
+
+impl TextFrames {
+    /// Keep every frame, e.g. for snapshot tests.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Write every frame followed by an empty line.
+    pub fn to_writer(output: Box<dyn Write + Send>) -> Self {
+        Self {
+            output: Some(output),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn frames(&self) -> &[String] {
+        &self.frames
+    }
+}
+
+impl FrameSink for TextFrames {
+    fn present(&mut self, canvas: &Canvas) {
+        let frame = canvas.to_text();
+        match &mut self.output {
+            Some(output) => {
+                writeln!(output, "{}\n", frame).ok();
+                output.flush().ok();
+            }
+            None => self.frames.push(frame),
+        }
+    }
+}
+
+/// Writes frames with 256-color escape codes, no raw mode needed.
+pub struct AnsiOutput(pub Box<dyn Write + Send>);
+
+impl FrameSink for AnsiOutput {
+    fn present(&mut self, canvas: &Canvas) {
+        let mut frame = String::from("\x1b[H\x1b[2J");
+        for row in canvas.rows() {
+            let mut color = None;
+            for cell in row {
+                if cell.color != color {
+                    match cell.color {
+                        Some(code) => frame.push_str(&format!("\x1b[38;5;{}m", code)),
+                        None => frame.push_str("\x1b[0m"),
+                    }
+                    color = cell.color;
+                }
+                frame.push(cell.ch);
+            }
+            frame.push_str("\x1b[0m\n");
+        }
+        self.0.write_all(frame.as_bytes()).ok();
+        self.0.flush().ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        brick::Brick,
+        game::{greedy_playout, GameState},
+        game_io::RenderGame,
+        vec2::Vec2,
+    };
+
+    use super::{ansi_256, parse_colors, AnsiOutput, AnsiRenderer, TextFrames, TextRenderer, BRICK_COLORS};
+
+    #[test]
+    fn test_config_colors() {
+        assert_eq!(parse_colors(include_str!("game.config.js")), vec![[0x00, 0xb0, 0x50]; 4]);
+        assert_eq!(ansi_256([0x00, 0xb0, 0x50]), 35);
+        assert_eq!(ansi_256([255, 255, 255]), 231);
+        assert_eq!(*BRICK_COLORS, vec![35; 4]);
+    }
+
+    #[test]
+    fn test_text_frame() {
+        let mut renderer = TextRenderer::new(TextFrames::in_memory());
+        renderer.render_game(&greedy_playout(&GameState::initial_state(), 1));
+        renderer.render_ghost(&Brick(4, 0), Vec2(4, 17));
+        renderer.render_brick(&Brick(4, 0), Vec2(4, 1));
+        renderer.render_message("hello");
+        renderer.flush();
+        renderer.render_lines(&["a".to_string(), "b".to_string()]);
+        renderer.flush();
+
+        let frames = renderer.sink().frames();
+        assert_eq!(frames.len(), 2);
+        let lines = frames[0].lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 21);
+        assert_eq!(lines[0], "    **    |");
+        assert_eq!(lines[1], "    **    |");
+        assert_eq!(lines[2], "          | Score: 0");
+        assert_eq!(lines[16], "    ..    |");
+        assert_eq!(lines[19], "        **| hello");
+        assert_eq!(lines[20], "----------");
+        assert_eq!(frames[1], "a\nb");
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ansi_colors() {
+        let buffer = SharedBuffer::default();
+        let mut renderer = AnsiRenderer::new(AnsiOutput(Box::new(buffer.clone())));
+        renderer.render_game(&GameState::initial_state());
+        renderer.render_brick(&Brick(0, 0), Vec2(4, 2));
+        renderer.render_hint(&Brick(0, 0), Vec2(4, 18));
+        renderer.flush();
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.starts_with("\x1b[H\x1b[2J"));
+        assert!(output.contains("\x1b[38;5;35m*"));
+        assert!(output.contains("\x1b[38;5;220m+"));
+    }
+}
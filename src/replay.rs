@@ -0,0 +1,154 @@
+use std::{fs, io, str::FromStr};
+
+use crate::{
+    brick::Brick,
+    game_play::Game,
+    grid::GameGrids,
+    op::{self, GameOP},
+    vec2::Vec2,
+};
+
+/// When a replay yields a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameStep {
+    /// After every op of the sequence.
+    PerOp,
+    /// After every brick that locks.
+    PerBrick,
+}
+
+impl FromStr for FrameStep {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "op" => Ok(Self::PerOp),
+            "brick" => Ok(Self::PerBrick),
+            _ => Err(format!("unknown frame step: {}", text)),
+        }
+    }
+}
+
+/// What is on screen at one step of a replay.
+#[derive(Clone)]
+pub struct Frame {
+    pub grids: GameGrids,
+    pub score: u32,
+    pub brick_count: usize,
+    /// The falling brick, `None` once the game is over.
+    pub brick: Option<(Brick, Vec2)>,
+    /// Number of ops applied so far.
+    pub ops_applied: usize,
+}
+
+/// Steps through an op sequence with the rules of game.core.js, see `Game::update`.
+///
+/// The first frame shows the first brick right after it spawned.
+pub struct Replay {
+    game: Game,
+    ops: Vec<GameOP>,
+    next_op: usize,
+    step: FrameStep,
+    started: bool,
+}
+
+impl Replay {
+    /// `ops` has to start with the `N` that spawns the first brick. The last
+    /// brick is locked at the end even without a trailing `N`.
+    pub fn new(mut ops: Vec<GameOP>, step: FrameStep) -> io::Result<Self> {
+        if ops.first() != Some(&GameOP::New) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "op sequence must start with N"));
+        }
+        op::lock_last_brick(&mut ops);
+        Ok(Self {
+            game: Game::new(),
+            ops,
+            next_op: 1,
+            step,
+            started: false,
+        })
+    }
+
+    pub fn load(path: &str, step: FrameStep) -> io::Result<Self> {
+        Self::new(op::parse_op_sequence(&fs::read_to_string(path)?)?, step)
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    fn frame(&self) -> Frame {
+        let state = self.game.state();
+        Frame {
+            grids: state.grids.clone(),
+            score: state.score,
+            brick_count: state.brick_count,
+            brick: self.game.current_brick(),
+            ops_applied: self.next_op,
+        }
+    }
+}
+
+impl Iterator for Replay {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if !self.started {
+            self.started = true;
+            return Some(self.frame());
+        }
+        while let Some(op) = self.ops.get(self.next_op) {
+            self.next_op += 1;
+            self.game.update(*op);
+            if self.step == FrameStep::PerOp || *op == GameOP::New {
+                return Some(self.frame());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        game::{greedy_playout, GameState},
+        op::{parse_op_sequence, GameOP},
+    };
+
+    use super::{FrameStep, Replay};
+
+    fn ops() -> Vec<GameOP> {
+        let mut ops = greedy_playout(&GameState::initial_state(), 5).get_op_sequence();
+        ops.push(GameOP::New);
+        ops
+    }
+
+    #[test]
+    fn test_steps() {
+        let ops = ops();
+        let per_op = Replay::new(ops.clone(), FrameStep::PerOp).unwrap().collect::<Vec<_>>();
+        assert_eq!(per_op.len(), ops.len());
+        let per_brick = Replay::new(ops.clone(), FrameStep::PerBrick).unwrap().collect::<Vec<_>>();
+        assert_eq!(per_brick.len(), 6);
+        assert_eq!(
+            per_brick.iter().map(|frame| frame.brick_count).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        let last = per_brick.last().unwrap();
+        assert_eq!(last.grids, per_op.last().unwrap().grids);
+        assert_eq!(last.ops_applied, ops.len());
+
+        // Solver output has no trailing `N`, its last brick is locked anyway.
+        let solver_ops = ops[..ops.len() - 1].to_vec();
+        let frames = Replay::new(solver_ops, FrameStep::PerBrick).unwrap().collect::<Vec<_>>();
+        assert_eq!(frames.len(), 6);
+        assert_eq!(frames.last().unwrap().grids, last.grids);
+        assert_eq!(frames.last().unwrap().score, last.score);
+    }
+
+    #[test]
+    fn test_must_start_with_new() {
+        assert!(Replay::new(parse_op_sequence("L1,N").unwrap(), FrameStep::PerOp).is_err());
+        assert!(Replay::new(Vec::new(), FrameStep::PerOp).is_err());
+    }
+}
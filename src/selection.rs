@@ -0,0 +1,274 @@
+//! Policies choosing the next layer of the beam among the children of the
+//! current one.
+//!
+//! Ranking purely by `sp_score` lets the beam collapse onto near-identical
+//! boards, the policies besides `Jitter` trade some score for keeping
+//! different boards alive.
+
+use std::{cmp::Reverse, collections::HashMap, fmt, str::FromStr};
+
+use rand::Rng;
+
+use crate::{game::Candidate, grid::GameGrids};
+
+/// Column heights above the lowest column are capped to this in a niche key.
+const MAX_RELATIVE_HEIGHT: u8 = 7;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Selection {
+    /// The best children by `sp_score` with ±2% random jitter.
+    #[default]
+    Jitter,
+    /// Like `Jitter`, but at most this many children of the same column
+    /// height profile, see `niche_key`.
+    Niche(usize),
+    /// Like `Jitter`, but at most this many children of the same parent.
+    ParentCap(usize),
+    /// Like `Jitter`, ranked by `sp_score` plus up to this bonus for children
+    /// whose height profile few others in the layer share.
+    Novelty(i32),
+    /// Repeatedly the best of this many random children, without jitter.
+    Tournament(usize),
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Jitter => write!(f, "jitter"),
+            Self::Niche(size) => write!(f, "niche:{}", size),
+            Self::ParentCap(cap) => write!(f, "parent-cap:{}", cap),
+            Self::Novelty(weight) => write!(f, "novelty:{}", weight),
+            Self::Tournament(size) => write!(f, "tournament:{}", size),
+        }
+    }
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid selection: {}", text);
+        let (name, value) = text.split_once(':').unwrap_or((text, ""));
+        let count = || value.parse::<usize>().ok().filter(|count| *count > 0).ok_or_else(invalid);
+        match name {
+            "jitter" if value.is_empty() => Ok(Self::Jitter),
+            "niche" => Ok(Self::Niche(count()?)),
+            "parent-cap" => Ok(Self::ParentCap(count()?)),
+            "novelty" => Ok(Self::Novelty(value.parse().map_err(|_| invalid())?)),
+            "tournament" => Ok(Self::Tournament(count()?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Selection {
+    /// Whether the children are jittered before they are selected.
+    pub fn jitters(&self) -> bool {
+        !matches!(self, Self::Tournament(_))
+    }
+}
+
+/// A scored child of the beam state at index `parent` of its layer.
+#[derive(Clone, Default)]
+pub struct Child {
+    pub parent: usize,
+    pub candidate: Candidate,
+}
+
+/// The column heights relative to the lowest column, each capped to
+/// `MAX_RELATIVE_HEIGHT` and packed in 3 bits. Boards that differ only by
+/// complete rows or deep wells fall into the same niche.
+pub fn niche_key(grids: &GameGrids) -> u32 {
+    let heights = grids.column_heights();
+    let lowest = heights.iter().min().copied().unwrap_or_default();
+    heights.iter().fold(0, |key, height| {
+        key << 3 | (height - lowest).min(MAX_RELATIVE_HEIGHT) as u32
+    })
+}
+
+/// Move the children `selection` keeps for a layer of `width` to the front of
+/// `children` and return how many they are.
+pub fn select(children: &mut [Child], width: usize, selection: Selection, rng: &mut impl Rng) -> usize {
+    let width = width.min(children.len());
+    let sp_score = |child: &Child| child.candidate.sp_score;
+    match selection {
+        Selection::Jitter => {
+            if width < children.len() {
+                children.select_nth_unstable_by_key(width, |child| Reverse(sp_score(child)));
+            }
+            width
+        }
+        Selection::Niche(size) => {
+            let mut niches = HashMap::new();
+            keep_best_capped(children, width, |child| {
+                let count = niches.entry(niche_key(&child.candidate.grids)).or_insert(0);
+                *count += 1;
+                *count <= size
+            })
+        }
+        Selection::ParentCap(cap) => {
+            let mut parents = HashMap::new();
+            keep_best_capped(children, width, |child| {
+                let count = parents.entry(child.parent).or_insert(0);
+                *count += 1;
+                *count <= cap
+            })
+        }
+        Selection::Novelty(weight) => {
+            let keys = children.iter().map(|child| niche_key(&child.candidate.grids)).collect::<Vec<_>>();
+            let mut niches = HashMap::<u32, i32>::new();
+            for key in &keys {
+                *niches.entry(*key).or_default() += 1;
+            }
+            let mut ranked = children
+                .iter()
+                .zip(&keys)
+                .map(|(child, key)| sp_score(child).saturating_add(weight / niches[key]))
+                .enumerate()
+                .collect::<Vec<_>>();
+            ranked.sort_unstable_by_key(|(_, score)| Reverse(*score));
+            move_to_front(children, ranked.iter().take(width).map(|(idx, _)| *idx))
+        }
+        Selection::Tournament(size) => {
+            for kept in 0..width {
+                let winner = (0..size)
+                    .map(|_| rng.gen_range(kept..children.len()))
+                    .max_by_key(|idx| sp_score(&children[*idx]))
+                    .unwrap_or(kept);
+                children.swap(kept, winner);
+            }
+            width
+        }
+    }
+}
+
+/// Keep the best children by `sp_score` that `admit` lets in, up to `width`.
+fn keep_best_capped(children: &mut [Child], width: usize, mut admit: impl FnMut(&Child) -> bool) -> usize {
+    children.sort_unstable_by_key(|child| Reverse(child.candidate.sp_score));
+    let mut kept = 0;
+    for idx in 0..children.len() {
+        if kept == width {
+            break;
+        }
+        if admit(&children[idx]) {
+            children.swap(kept, idx);
+            kept += 1;
+        }
+    }
+    kept
+}
+
+/// Move the children at the distinct indices `kept` to the front.
+fn move_to_front(children: &mut [Child], kept: impl Iterator<Item = usize>) -> usize {
+    let mut keep = vec![false; children.len()];
+    for idx in kept {
+        keep[idx] = true;
+    }
+    let mut len = 0;
+    for (idx, keep) in keep.into_iter().enumerate() {
+        if keep {
+            children.swap(len, idx);
+            len += 1;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::game::{Candidate, GameState, MAX_PLACEMENTS};
+
+    use super::{niche_key, select, Child, Selection};
+
+    /// The children of a few states a couple of bricks into a game.
+    fn children() -> Vec<Child> {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let len = GameState::initial_state().next(&mut next_states);
+        let mut candidates = vec![Candidate::default(); MAX_PLACEMENTS];
+        let mut children = Vec::new();
+        for (parent, state) in next_states[..len].iter().enumerate().take(6) {
+            let len = state.candidates(&mut candidates);
+            children.extend(candidates[..len].iter().map(|candidate| Child { parent, candidate: candidate.clone() }));
+        }
+        children
+    }
+
+    fn counts<K: std::hash::Hash + Eq>(keys: impl Iterator<Item = K>) -> HashMap<K, usize> {
+        let mut counts = HashMap::new();
+        for key in keys {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_parse() {
+        for text in ["jitter", "niche:4", "parent-cap:3", "novelty:-20", "tournament:8"] {
+            assert_eq!(text.parse::<Selection>().unwrap().to_string(), text);
+        }
+        for text in ["", "jitter:1", "niche", "niche:0", "tournament:x", "best"] {
+            assert!(text.parse::<Selection>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_niche_key() {
+        let mut state = GameState::initial_state();
+        assert_eq!(niche_key(&state.grids), 0);
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let len = state.next(&mut next_states);
+        state = next_states[..len].iter().max_by_key(|state| state.sp_score).unwrap().clone();
+        assert_ne!(niche_key(&state.grids), 0);
+    }
+
+    #[test]
+    fn test_caps() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut children = children();
+        let best = children.iter().map(|child| child.candidate.sp_score).max().unwrap();
+
+        let len = select(&mut children, 20, Selection::ParentCap(2), &mut rng);
+        assert_eq!(len, 12);
+        assert!(counts(children[..len].iter().map(|child| child.parent)).values().all(|count| *count == 2));
+        assert!(children[..len].iter().any(|child| child.candidate.sp_score == best));
+
+        let len = select(&mut children, 20, Selection::Niche(1), &mut rng);
+        let niches = counts(children[..len].iter().map(|child| niche_key(&child.candidate.grids)));
+        assert_eq!(niches.len(), len);
+    }
+
+    #[test]
+    fn test_novelty_and_tournament() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut children = children();
+        let total = children.len();
+        let mut best = children.iter().map(|child| child.candidate.sp_score).collect::<Vec<_>>();
+        best.sort_unstable();
+        best.reverse();
+
+        assert_eq!(select(&mut children, 10, Selection::Novelty(0), &mut rng), 10);
+        let mut kept = children[..10].iter().map(|child| child.candidate.sp_score).collect::<Vec<_>>();
+        kept.sort_unstable();
+        kept.reverse();
+        assert_eq!(kept, best[..10]);
+
+        // A large bonus prefers profiles that are unique in the layer.
+        let niches = counts(children.iter().map(|child| niche_key(&child.candidate.grids)));
+        let unique = niches.values().filter(|count| **count == 1).count().min(10);
+        select(&mut children, 10, Selection::Novelty(1_000_000), &mut rng);
+        let is_unique = |child: &&Child| niches[&niche_key(&child.candidate.grids)] == 1;
+        assert_eq!(children[..10].iter().filter(is_unique).count(), unique);
+
+        assert_eq!(select(&mut children, total + 5, Selection::Tournament(4), &mut rng), total);
+        let len = select(&mut children, 10, Selection::Tournament(total * 10), &mut rng);
+        let mut kept = children[..len].iter().map(|child| child.candidate.sp_score).collect::<Vec<_>>();
+        kept.sort_unstable();
+        kept.reverse();
+        // Large tournaments almost always pick the best remaining child.
+        assert_eq!(kept[0], best[0]);
+    }
+}
@@ -0,0 +1,175 @@
+//! Versioned JSON and binary encodings of the game types, with the `serde`
+//! feature.
+//!
+//! Both wrap the value in an envelope carrying `FORMAT_VERSION`:
+//!
+//! ```text
+//! JSON    {"version":1,"payload":<value>}
+//! binary  <version: u32 little-endian> <value in bincode 1.x default options>
+//! ```
+//!
+//! A `GameGrids` is written as its 20 rows from the top in JSON, each like
+//! `..##.#####` (see `GameGrids::to_ascii`), and as the 5 words of
+//! `GameGrids::bits` in binary. `brick_stack` entries stay in their compact
+//! u16 form: x in bits 0..4, y in bits 4..9 and the number of rotations in
+//! bits 10..14, see `encode_placement`.
+
+use std::{convert::TryFrom, io};
+
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{brick::Brick, grid::GameGrids};
+
+/// Version of the encodings, bumped whenever a change breaks reading older data.
+pub const FORMAT_VERSION: u32 = 1;
+/// Cells of the board in the layout of one word of `GameGrids::bits`.
+const BOARD_CELLS: u64 = 0x03FF_03FF_03FF_03FF;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    payload: T,
+}
+
+fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+fn check_version(version: u32) -> io::Result<()> {
+    match version {
+        FORMAT_VERSION => Ok(()),
+        _ => Err(invalid_data(format!(
+            "unsupported format version {}, expected {}",
+            version, FORMAT_VERSION
+        ))),
+    }
+}
+
+pub fn to_json<T: Serialize>(value: &T) -> io::Result<String> {
+    serde_json::to_string(&Envelope { version: FORMAT_VERSION, payload: value }).map_err(invalid_data)
+}
+
+pub fn from_json<T: DeserializeOwned>(text: &str) -> io::Result<T> {
+    let envelope: Envelope<serde_json::Value> = serde_json::from_str(text).map_err(invalid_data)?;
+    check_version(envelope.version)?;
+    serde_json::from_value(envelope.payload).map_err(invalid_data)
+}
+
+pub fn to_binary<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(&Envelope { version: FORMAT_VERSION, payload: value }).map_err(invalid_data)
+}
+
+pub fn from_binary<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    let version = bytes
+        .get(..4)
+        .ok_or_else(|| invalid_data("missing format version"))?;
+    check_version(u32::from_le_bytes([version[0], version[1], version[2], version[3]]))?;
+    bincode::deserialize(&bytes[4..]).map_err(invalid_data)
+}
+
+impl Serialize for GameGrids {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            self.to_ascii().lines().collect::<Vec<_>>().serialize(serializer)
+        } else {
+            self.bits().serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GameGrids {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let rows = Vec::<String>::deserialize(deserializer)?;
+            Self::from_ascii(&rows.join("\n")).map_err(de::Error::custom)
+        } else {
+            let bits = <[u64; 5]>::deserialize(deserializer)?;
+            if bits.iter().any(|bits| bits & !BOARD_CELLS != 0) {
+                return Err(de::Error::custom("cells outside the board"));
+            }
+            Ok(Self::from_bits(bits))
+        }
+    }
+}
+
+impl TryFrom<(usize, usize)> for Brick {
+    type Error = String;
+
+    fn try_from((shape, state): (usize, usize)) -> Result<Self, Self::Error> {
+        match (shape, state) {
+            (0..=6, 0..=3) => Ok(Brick(shape, state)),
+            _ => Err(format!("invalid brick: shape {}, state {}", shape, state)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        brick::Brick,
+        game::{GameState, MAX_PLACEMENTS},
+        op::GameOP,
+        vec2::Vec2,
+    };
+
+    use super::{from_binary, from_json, to_binary, to_json, FORMAT_VERSION};
+
+    fn played_state() -> GameState {
+        let mut next_states = vec![GameState::default(); MAX_PLACEMENTS];
+        let mut state = GameState::initial_state();
+        for _ in 0..12 {
+            let len = state.next(&mut next_states);
+            state = next_states[..len].iter().max_by_key(|state| state.sp_score).unwrap().clone();
+        }
+        state
+    }
+
+    fn assert_same(parsed: &GameState, state: &GameState) {
+        assert_eq!(parsed.grids, state.grids);
+        assert_eq!((parsed.score, parsed.sp_score, parsed.rand_num), (state.score, state.sp_score, state.rand_num));
+        assert_eq!((&parsed.brick_stack, parsed.brick_count), (&state.brick_stack, state.brick_count));
+    }
+
+    #[test]
+    fn test_json() {
+        let ops = vec![GameOP::New, GameOP::Left(2), GameOP::Rotate(1)];
+        let text = to_json(&(ops.clone(), Vec2(4, -1), Brick(2, 3))).unwrap();
+        assert_eq!(text, r#"{"version":1,"payload":[["New",{"Left":2},{"Rotate":1}],[4,-1],[2,3]]}"#);
+        let (parsed, pos, brick): (Vec<GameOP>, Vec2, Brick) = from_json(&text).unwrap();
+        assert_eq!((parsed, pos, (brick.0, brick.1)), (ops, Vec2(4, -1), (2, 3)));
+        assert!(from_json::<Brick>(r#"{"version":1,"payload":[7,0]}"#).is_err());
+
+        let state = played_state();
+        let text = to_json(&state).unwrap();
+        let row = state.grids.to_ascii().lines().last().unwrap().to_string();
+        assert!(text.contains(&format!(r#""{}"]"#, row)));
+        let placements = state.brick_stack.iter().map(u16::to_string).collect::<Vec<_>>();
+        assert!(text.contains(&format!(r#""brick_stack":[{}]"#, placements.join(","))));
+        assert_same(&from_json(&text).unwrap(), &state);
+    }
+
+    #[test]
+    fn test_binary() {
+        let state = played_state();
+        let bytes = to_binary(&state).unwrap();
+        assert_eq!(bytes[..4], FORMAT_VERSION.to_le_bytes());
+        assert_eq!(bytes[4..12], state.grids.bits()[0].to_le_bytes());
+        assert_same(&from_binary(&bytes).unwrap(), &state);
+        assert!(from_binary::<GameState>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut bytes = to_binary(&GameOP::Down(3)).unwrap();
+        assert_eq!(from_binary::<GameOP>(&bytes).unwrap(), GameOP::Down(3));
+        bytes[0] = 2;
+        let err = from_binary::<GameOP>(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported format version 2, expected 1");
+    }
+
+    #[test]
+    fn test_invalid_grids() {
+        let text = r#"{"version":1,"payload":["..........",".........."]}"#;
+        let err = from_json::<crate::grid::GameGrids>(text).unwrap_err();
+        assert_eq!(err.to_string(), "expected 20 rows, found 2");
+        let bytes = to_binary(&[u64::MAX; 5]).unwrap();
+        assert!(from_binary::<crate::grid::GameGrids>(&bytes).is_err());
+    }
+}